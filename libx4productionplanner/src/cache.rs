@@ -0,0 +1,60 @@
+// Parsed game data is stored on disk, so that the next run skips reading and
+// parsing the catalogs. The cache is valid while the key matches: the same
+// planner version, the same game directory, extensions and source files
+// (size and modification time of every catalog read).
+use crate::catalog::FileFingerprint;
+
+// Bump whenever parsing changes what the cached data holds, a cache of the
+// same shape would be read back otherwise
+pub const CACHE_FORMAT_VERSION: u32 = 2;
+
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct CacheKey {
+    pub version:    String,
+    pub format:     u32,
+    pub gamedir:    std::path::PathBuf,
+    pub unpacked:   bool,
+    pub extensions: Vec<String>,
+    pub files:      Vec<FileFingerprint>,
+}
+
+#[derive(serde::Serialize, serde::Deserialize)]
+struct CacheFile<T> {
+    key:  CacheKey,
+    data: T,
+}
+
+pub fn default_cache_dir () -> std::path::PathBuf {
+    std::env::temp_dir().join("x4productionplanner")
+}
+
+// One file per game directory, a new key just overwrites the previous one
+pub fn cache_file_path (cache_dir: &std::path::Path, key: &CacheKey) -> std::path::PathBuf {
+    use std::hash::{Hash, Hasher};
+
+    let mut hasher = std::collections::hash_map::DefaultHasher::new();
+    (&key.gamedir, key.unpacked).hash(&mut hasher);
+    cache_dir.join(format!("data-{:016x}.toml", hasher.finish()))
+}
+
+// None if there is no cache, it is outdated or unreadable
+pub fn load<T: serde::de::DeserializeOwned> (path: &std::path::Path, key: &CacheKey) -> Option<T> {
+    let content = std::fs::read_to_string(path).ok()?;
+    let cache_file = toml_edit::de::from_str::<CacheFile<T>>(&content).ok()?;
+    if &cache_file.key != key {
+        return None;
+    }
+    Some(cache_file.data)
+}
+
+pub fn store<T: serde::Serialize> (path: &std::path::Path, key: &CacheKey, data: &T) -> std::io::Result<()> {
+    let content = toml_edit::ser::to_string(&CacheFile{key: key.clone(), data})
+                      .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e))?;
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir)?;
+    }
+    // several planners may run at once, none should read a half-written file
+    let tmp_path = path.with_extension(format!("tmp{}", std::process::id()));
+    std::fs::write(&tmp_path, content)?;
+    std::fs::rename(&tmp_path, path)
+}
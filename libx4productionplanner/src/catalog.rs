@@ -0,0 +1,253 @@
+// X4 keeps its virtual filesystem in `NN.cat`/`NN.dat` pairs. The `.cat` is
+// a text index, one entry per line:
+// ```
+// libraries/wares.xml 2712364 1683547434 4a3ce2a7f2ff9b0d0b1c8e2f1c5e4a60
+// ```
+// (path, size in bytes, unix timestamp, md5 of the content), the `.dat` is the
+// content of all entries concatenated in the same order, so the offset of an
+// entry is the sum of sizes of all entries before it.
+use std::collections::HashMap;
+use std::io::{Read, Seek};
+
+#[derive(Debug)]
+pub enum CatalogError {
+    StdIo(std::io::Error),
+    BadLine(std::path::PathBuf, usize),
+    EntryNotFound(std::path::PathBuf, String),
+}
+impl From<std::io::Error> for CatalogError {
+    fn from(value: std::io::Error) -> Self {
+        Self::StdIo(value)
+    }
+}
+
+#[derive(Debug, Clone)]
+#[allow(dead_code)]
+pub struct CatalogEntry {
+    pub path:      String,
+    pub size:      u64,
+    pub timestamp: u64,
+    pub hash:      String,
+    pub offset:    u64,
+}
+
+#[derive(Debug, Clone)]
+pub struct Catalog {
+    pub cat_path: std::path::PathBuf,
+    pub dat_path: std::path::PathBuf,
+    pub entries:  Vec<CatalogEntry>,
+}
+impl Catalog {
+    fn parse_line (line: &str, offset: u64) -> Option<CatalogEntry> {
+        // path may contain spaces, so reading from the end
+        let mut it = line.rsplitn(4, ' ');
+        let hash      = it.next()?.to_string();
+        let timestamp = it.next()?.parse().ok()?;
+        let size      = it.next()?.parse().ok()?;
+        let path      = it.next()?.to_string();
+        if path.is_empty() {
+            return None;
+        }
+        Some(CatalogEntry{path, size, timestamp, hash, offset})
+    }
+    pub fn parse_str (content: &str, cat_path: std::path::PathBuf, dat_path: std::path::PathBuf) -> Result<Self, CatalogError> {
+        let mut entries = Vec::new();
+        let mut offset = 0;
+        for (line_idx, line) in content.lines().enumerate() {
+            let line = line.trim_end();
+            if line.is_empty() {
+                continue;
+            }
+            let entry = Self::parse_line(line, offset).ok_or(CatalogError::BadLine(cat_path.clone(), line_idx + 1))?;
+            offset += entry.size;
+            entries.push(entry);
+        }
+        Ok(Self{cat_path, dat_path, entries})
+    }
+    pub fn load (cat_path: &std::path::Path) -> Result<Self, CatalogError> {
+        let content = std::fs::read(cat_path)?;
+        let content = String::from_utf8_lossy(&content);
+        Self::parse_str(&content, cat_path.to_path_buf(), cat_path.with_extension("dat"))
+    }
+    pub fn read_entry (&self, entry: &CatalogEntry) -> Result<Vec<u8>, CatalogError> {
+        let mut file = std::fs::File::open(&self.dat_path)?;
+        file.seek(std::io::SeekFrom::Start(entry.offset))?;
+        let mut content = vec![0; entry.size as usize];
+        file.read_exact(&mut content)?;
+        Ok(content)
+    }
+}
+
+// All catalogs of a directory, where a path found in a later catalog overrides
+// the same path of an earlier one, as the game does
+#[derive(Debug, Clone)]
+pub struct CatalogSet {
+    catalogs: Vec<Catalog>,
+    // lowercase virtual path -> (catalog index, entry index)
+    index:    HashMap<String, (usize, usize)>,
+}
+impl CatalogSet {
+    fn catalog_number (file_name: &str, prefix: &str) -> Option<u32> {
+        // "08.cat" for prefix "", "ext_01.cat" for prefix "ext_"
+        // ("08_sig.cat" and similar are not catalogs)
+        file_name.strip_prefix(prefix)?.strip_suffix(".cat")?.parse().ok()
+    }
+    pub fn load_numbered (dir: &std::path::Path, prefix: &str) -> Result<Self, CatalogError> {
+        let mut numbered = Vec::new();
+        for dir_entry in std::fs::read_dir(dir)? {
+            let path = dir_entry?.path();
+            if let Some(number) = path.file_name().and_then(|name| name.to_str()).and_then(|name| Self::catalog_number(name, prefix)) {
+                numbered.push((number, path));
+            }
+        }
+        numbered.sort();
+        let catalogs = numbered.into_iter().map(|(_, path)| Catalog::load(&path)).collect::<Result<Vec<_>, _>>()?;
+        Ok(Self::new(catalogs))
+    }
+    pub fn new (catalogs: Vec<Catalog>) -> Self {
+        let mut index = HashMap::new();
+        for (catalog_idx, catalog) in catalogs.iter().enumerate() {
+            for (entry_idx, entry) in catalog.entries.iter().enumerate() {
+                index.insert(entry.path.to_ascii_lowercase(), (catalog_idx, entry_idx));
+            }
+        }
+        Self{catalogs, index}
+    }
+    pub fn is_empty (&self) -> bool {
+        self.catalogs.is_empty()
+    }
+    pub fn find (&self, path: &str) -> Option<(&Catalog, &CatalogEntry)> {
+        let (catalog_idx, entry_idx) = self.index.get(&path.to_ascii_lowercase())?;
+        let catalog = &self.catalogs[*catalog_idx];
+        Some((catalog, &catalog.entries[*entry_idx]))
+    }
+    pub fn read (&self, path: &str) -> Result<(Vec<u8>, &std::path::Path), CatalogError> {
+        let (catalog, entry) = self.find(path).ok_or(CatalogError::EntryNotFound(
+                                   self.catalogs.last().map(|catalog| catalog.cat_path.clone()).unwrap_or_default(),
+                                   path.to_string()
+                                   ))?;
+        Ok((catalog.read_entry(entry)?, &catalog.cat_path))
+    }
+    pub fn source (&self, path: &str) -> Option<&std::path::Path> {
+        self.find(path).map(|(catalog, _)| catalog.cat_path.as_path())
+    }
+    pub fn fingerprint (&self) -> Result<Vec<FileFingerprint>, CatalogError> {
+        self.catalogs.iter()
+            .flat_map(|catalog| [&catalog.cat_path, &catalog.dat_path])
+            .map(|path| file_fingerprint(path))
+            .collect()
+    }
+}
+
+// (file, size, modification time in nanoseconds), changes when the file does
+pub type FileFingerprint = (std::path::PathBuf, u64, u64);
+
+fn file_fingerprint (path: &std::path::Path) -> Result<FileFingerprint, CatalogError> {
+    let metadata = std::fs::metadata(path)?;
+    let modified = metadata.modified()?.duration_since(std::time::UNIX_EPOCH).map_or(0, |duration| duration.as_nanos() as u64);
+    Ok((path.to_path_buf(), metadata.len(), modified))
+}
+
+// Game files either packed in catalogs or extracted by X Catalog Tool into
+// a directory tree with the same virtual paths
+#[derive(Debug, Clone)]
+pub enum GameFiles {
+    Catalogs(CatalogSet),
+    Unpacked(std::path::PathBuf),
+}
+impl GameFiles {
+    pub fn is_empty (&self) -> bool {
+        match self {
+            Self::Catalogs(catalogs) => catalogs.is_empty(),
+            Self::Unpacked(dir) => !dir.is_dir(),
+        }
+    }
+    pub fn contains (&self, path: &str) -> bool {
+        self.source(path).is_some()
+    }
+    pub fn read (&self, path: &str) -> Result<(Vec<u8>, std::path::PathBuf), CatalogError> {
+        match self {
+            Self::Catalogs(catalogs) => catalogs.read(path).map(|(content, cat_path)| (content, cat_path.join(path))),
+            Self::Unpacked(dir) => {
+                let file_path = dir.join(path);
+                match std::fs::read(&file_path) {
+                    Ok(content) => Ok((content, file_path)),
+                    Err(e) if e.kind() == std::io::ErrorKind::NotFound => Err(CatalogError::EntryNotFound(dir.clone(), path.to_string())),
+                    Err(e) => Err(e.into()),
+                }
+            },
+        }
+    }
+    // Fingerprints of everything the files are read from: all catalogs or,
+    // if unpacked, the given paths (directories with all their files)
+    pub fn fingerprint (&self, unpacked_paths: &[&str]) -> Result<Vec<FileFingerprint>, CatalogError> {
+        match self {
+            Self::Catalogs(catalogs) => catalogs.fingerprint(),
+            Self::Unpacked(dir) => {
+                let mut result = Vec::new();
+                for path in unpacked_paths.iter().map(|path| dir.join(path)) {
+                    if path.is_dir() {
+                        let mut files = std::fs::read_dir(&path)?.map(|dir_entry| dir_entry.map(|dir_entry| dir_entry.path())).collect::<Result<Vec<_>, _>>()?;
+                        files.sort();
+                        for file in files.iter().filter(|file| file.is_file()) {
+                            result.push(file_fingerprint(file)?);
+                        }
+                    }
+                    else if path.is_file() {
+                        result.push(file_fingerprint(&path)?);
+                    }
+                }
+                Ok(result)
+            },
+        }
+    }
+    // catalog the file is taken from or the file itself if unpacked
+    pub fn source (&self, path: &str) -> Option<std::path::PathBuf> {
+        match self {
+            Self::Catalogs(catalogs) => catalogs.source(path).map(|cat_path| cat_path.to_path_buf()),
+            Self::Unpacked(dir) => Some(dir.join(path)).filter(|file_path| file_path.is_file()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_catalog_read () {
+        let dir = std::env::temp_dir().join(format!("x4pp-catalog-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let cat_path = dir.join("01.cat");
+        std::fs::write(&cat_path, "index/macros.xml 5 1683547434 00000000000000000000000000000000\nt/my file.xml 3 1683547434 00000000000000000000000000000000\n").unwrap();
+        std::fs::write(dir.join("01.dat"), "hellobye").unwrap();
+
+        let catalog = Catalog::load(&cat_path).unwrap();
+        assert_eq!(catalog.entries.len(), 2);
+        assert_eq!(catalog.entries[1].path, "t/my file.xml");
+        assert_eq!(catalog.entries[1].offset, 5);
+        assert_eq!(catalog.read_entry(&catalog.entries[0]).unwrap(), b"hello");
+        assert_eq!(catalog.read_entry(&catalog.entries[1]).unwrap(), b"bye");
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+    #[test]
+    fn test_catalog_set_override () {
+        let dir = std::env::temp_dir().join(format!("x4pp-catalogset-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("01.cat"), "a.xml 3 0 0\nb.xml 3 0 0\n").unwrap();
+        std::fs::write(dir.join("01.dat"), "a01b01").unwrap();
+        std::fs::write(dir.join("10.cat"), "b.xml 3 0 0\n").unwrap();
+        std::fs::write(dir.join("10.dat"), "b10").unwrap();
+        std::fs::write(dir.join("02.cat"), "b.xml 3 0 0\n").unwrap();
+        std::fs::write(dir.join("02.dat"), "b02").unwrap();
+        std::fs::write(dir.join("10_sig.cat"), "not a catalog").unwrap();
+
+        let set = CatalogSet::load_numbered(&dir, "").unwrap();
+        assert_eq!(set.read("a.xml").unwrap().0, b"a01");
+        assert_eq!(set.read("b.xml").unwrap().0, b"b10");
+        assert_eq!(set.source("b.xml").unwrap(), dir.join("10.cat"));
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
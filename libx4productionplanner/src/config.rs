@@ -22,10 +22,10 @@ impl From<std::io::Error> for ConfigError {
     }
 }
 
-const DEFAULT_LANG: &'static str = "ru";
+const DEFAULT_LANG: &str = "ru";
 
 fn get_dir_for_platform_default () -> Result<std::path::PathBuf, ConfigError> {
-    if cfg!(linux) {
+    if cfg!(target_os = "linux") {
         return Ok("~/.local/share/Steam/steamapps/common/X4 Foundations/".into());
     }
     Err(ConfigError::UnknownPlatform(std::env::consts::OS.into()))
@@ -142,7 +142,7 @@ impl Config {
         }
     }
     pub fn load_str (&mut self, input: &str, usr_config_path: Option<std::path::PathBuf>) -> Result<(), ConfigError> {
-        let serialized = toml_edit::Document::from_str(&input)
+        let serialized = toml_edit::Document::from_str(input)
                              .map_err(|_| ConfigError::BadConfigNotToml)?;
 
         let mut candidate = Self::new_from_toml(
@@ -207,7 +207,7 @@ fn my_lang_deser<'de, D: serde::de::Deserializer<'de>> (de: D) -> Result<(bool,
             formatter.write_str(BADLANG_STR)
         }
         fn visit_str<E: serde::de::Error> (self, v: &str) -> Result<Self::Value, E> {
-            let id = v.parse().map_err(|_| (E::invalid_value(serde::de::Unexpected::Str(v), &self)))?;
+            let id = v.parse().map_err(|_| E::invalid_value(serde::de::Unexpected::Str(v), &self))?;
             Ok((v.to_string(), id))
         }
     }
@@ -269,7 +269,7 @@ mod tests {
         let lang_key = toml_edit::Key::new("lang").with_decor(toml_edit::Decor::new("# the desired language\n", ""));
         let lang_value = std::str::FromStr::from_str("\"ru\"").unwrap();
         doc.insert_formatted(&lang_key, lang_value);
-        println!("Serialized: \"\n{}\n\"", doc.to_string());
+        println!("Serialized: \"\n{}\n\"", doc);
         println!("Parsed: \"{:?}\"", toml_edit::de::from_document::<Config>(doc));
     }
     #[test]
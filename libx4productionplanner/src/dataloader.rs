@@ -1,8 +1,22 @@
 use std::cell::Cell;
 use std::collections::HashMap;
 
-const TRANSLATIONS_FILE: &str = "09.dat";
-const PRODUCTION_FILE:   &str = "08.dat";
+use crate::cache::{self, CacheKey};
+use crate::catalog::{CatalogError, CatalogSet, GameFiles};
+use crate::xmldiff::XmlDiffError;
+
+const WARES_PATH:     &str = "libraries/wares.xml";
+const LANGUAGES_PATH: &str = "libraries/languages.xml";
+const MACROS_INDEX_PATH:     &str = "index/macros.xml";
+const PRODUCTION_MACROS_DIR: &str = "assets/structures/production/macros";
+const STORAGE_MACROS_DIR:    &str = "assets/structures/storage/macros";
+const HABITAT_MACROS_DIR:    &str = "assets/structures/habitat/macros";
+const EXTENSIONS_DIR: &str = "extensions";
+
+fn translation_path (x4_lang_id: &str) -> String {
+    // e.g. "t/0001-l044.xml" for english, "t/0001-l007.xml" for russian
+    format!("t/0001-l{:0>3}.xml", x4_lang_id)
+}
 
 /*
 // I have no idea how to map this in runtime
@@ -40,6 +54,8 @@ const LANGUAGE_ID_TO_LOCALE_ID: [(&str, &str); 12] = [
 pub enum DataError {
     StdIo(std::io::Error),
     InvalidXml(std::path::PathBuf, roxmltree::Error),
+    CatalogError(CatalogError),
+    XmlDiffError(std::path::PathBuf, XmlDiffError),
     AttributeNotFound(&'static str, String),
     AttributeBadValue(&'static str, String, &'static str),
     TagNotFound(&'static str, String),
@@ -60,6 +76,16 @@ pub enum DataError {
     NoProductionMethods(String),
 
     InconsistentRequest(&'static str),
+    // (ware_id, method) without a module ware making it
+    ModuleNotFound(String, String),
+    SolverFailed(String),
+    // ware ids from the first to the repeated one
+    RecipeCycle(Vec<String>),
+    // workers and construction of the modules added for them need more
+    // modules than they add
+    StationNeedsDiverge,
+    // the habitat module asked for, none if there are no habitat macros
+    HabitatNotFound(Option<String>),
 
     TranslationError(String),
 }
@@ -68,6 +94,11 @@ impl From<std::io::Error> for DataError {
         Self::StdIo(value)
     }
 }
+impl From<CatalogError> for DataError {
+    fn from(value: CatalogError) -> Self {
+        Self::CatalogError(value)
+    }
+}
 
 fn mygetatr<'a> (node: roxmltree::Node<'a, 'static>, atr: &'static str) -> Result<&'a str, DataError> {
     node.attribute(atr).ok_or(
@@ -84,40 +115,113 @@ fn mygetatrparsed<'a, F: std::str::FromStr> (node: roxmltree::Node<'a, 'static>,
             )
         )
 }
+// the attribute of the node or, if the node omits it, of `<defaults>`
+fn mygetatrinherited<'a> (node: roxmltree::Node<'a, 'static>, defaults: &'a HashMap<String, String>, atr: &'static str) -> Result<&'a str, DataError> {
+    node.attribute(atr).or(defaults.get(atr).map(|value| value.as_str())).ok_or(
+        DataError::AttributeNotFound(atr, format!("{:?}", node))
+        )
+}
+fn mygetatrinheritedparsed<'a, F: std::str::FromStr> (node: roxmltree::Node<'a, 'static>, defaults: &'a HashMap<String, String>, atr: &'static str) -> Result<F, DataError> {
+    mygetatrinherited(node, defaults, atr)?.parse().map_err(
+        |_|
+        DataError::AttributeBadValue(
+            atr,
+            format!("{:?}", node),
+            std::any::type_name::<F>()
+            )
+        )
+}
 fn myfindchildtag<'a> (node: roxmltree::Node<'a, 'static>, tag: &'static str) -> Result<roxmltree::Node<'a, 'static>, DataError> {
     node.children().find(|n|n.has_tag_name(tag))
         .ok_or(DataError::TagNotFound(tag, format!("{:?}", node)))
 }
 
-pub fn check_gamedir (path: &std::path::Path) -> Result<(), crate::config::ConfigError> {
+fn check_game_files (files: &GameFiles) -> Result<(), crate::config::ConfigError> {
     use crate::config::ConfigError;
 
-    let mut path = path.to_path_buf();
-    path.push(TRANSLATIONS_FILE);
-    if !path.is_file() {
+    if files.is_empty() {
+        return Err(ConfigError::BadGamedir("catalogs".into()));
+    }
+    if !files.contains(&translation_path("44")) {
         return Err(ConfigError::BadGamedir("translation-file".into()));
     }
-
-    let mut path = path.to_path_buf();
-    path.push(PRODUCTION_FILE);
-    if !path.is_file() {
+    if !files.contains(WARES_PATH) || !files.contains(LANGUAGES_PATH) {
         return Err(ConfigError::BadGamedir("production-file".into()));
     }
 
     Ok(())
 }
+pub fn check_gamedir (path: &std::path::Path) -> Result<(), crate::config::ConfigError> {
+    use crate::config::ConfigError;
+
+    let catalogs = CatalogSet::load_numbered(path, "")
+                       .map_err(|_| ConfigError::BadGamedir("catalogs".into()))?;
+    check_game_files(&GameFiles::Catalogs(catalogs))
+}
+// Same as `check_gamedir` for the tree extracted by X Catalog Tool
+pub fn check_unpacked_gamedir (path: &std::path::Path) -> Result<(), crate::config::ConfigError> {
+    check_game_files(&GameFiles::Unpacked(path.to_path_buf()))
+}
+
+#[derive(Debug, Clone, Default)]
+pub enum ExtensionsSelection {
+    #[default]
+    All,
+    Only(Vec<String>),
+    AllExcept(Vec<String>),
+}
+impl ExtensionsSelection {
+    fn is_enabled (&self, extension_id: &str) -> bool {
+        match self {
+            Self::All => true,
+            Self::Only(enabled) => enabled.iter().any(|id| id == extension_id),
+            Self::AllExcept(disabled) => !disabled.iter().any(|id| id == extension_id),
+        }
+    }
+}
+
+fn load_extensions_files (gamedir: &std::path::Path, selection: &ExtensionsSelection, is_unpacked: bool) -> Result<Vec<(String, GameFiles)>, DataError> {
+    // e.g. "extensions/ego_dlc_boron/ext_01.cat"
+    // or "extensions/ego_dlc_boron/libraries/wares.xml" if unpacked
+    let extensions_dir = gamedir.join(EXTENSIONS_DIR);
+    let mut result = Vec::new();
+    if !extensions_dir.is_dir() {
+        return Ok(result);
+    }
+    for dir_entry in std::fs::read_dir(extensions_dir)? {
+        let path = dir_entry?.path();
+        let extension_id = match path.file_name().and_then(|name| name.to_str()) {
+                               Some(name) if path.is_dir() => name.to_string(),
+                               _ => continue,
+                           };
+        if !selection.is_enabled(&extension_id) {
+            continue;
+        }
+        let files
+            = if is_unpacked {
+                  GameFiles::Unpacked(path)
+              }
+              else {
+                  GameFiles::Catalogs(CatalogSet::load_numbered(&path, "ext_")?)
+              };
+        if !files.is_empty() {
+            result.push((extension_id, files));
+        }
+    }
+    result.sort_by(|(a, _), (b, _)| a.cmp(b));
+    Ok(result)
+}
 
-fn load_string_file (dir: &std::path::Path, file: &str) -> Result<(String, std::path::PathBuf), DataError> {
-    let mut path = dir.to_path_buf();
-    path.push(file);
-    let content_bytes = std::fs::read(&path)?;
-    let content = String::from_utf8_lossy(&content_bytes).into();
-    Ok((content, path))
+fn load_game_string_file (files: &GameFiles, file: &str) -> Result<(String, std::path::PathBuf), DataError> {
+    let (content_bytes, dbg_path) = files.read(file)?;
+    let content = String::from_utf8_lossy(&content_bytes).trim_start_matches('\u{feff}').into();
+    Ok((content, dbg_path))
 }
+
 fn read_xml<Finder: Fn(&str)->Option<(&str, &str)>>(input_owned: String, finder_to_iter: Finder, dbg_name: &'static str, dbg_path: &std::path::Path) -> Result<Cell<(String, Vec<roxmltree::Document<'static>>)>, DataError> {
     let mut result_xmls = Vec::new();
     let mut remaining = input_owned.as_str();
-    while let Some((new_remaining, content_slice)) = finder_to_iter(&remaining) {
+    while let Some((new_remaining, content_slice)) = finder_to_iter(remaining) {
         let document = roxmltree::Document::parse(
                            // Safety: since we put String to Cell, the position
                            //         on heap is persistent, cell marks that
@@ -136,16 +240,24 @@ fn read_xml<Finder: Fn(&str)->Option<(&str, &str)>>(input_owned: String, finder_
     }
 }
 
+fn find_whole_xml_slice (input: &str) -> Option<(&str, &str)> {
+    if input.is_empty() {
+        None
+    }
+    else {
+        Some((&input[input.len()..], input))
+    }
+}
 fn find_translation_xml_slice (input: &str) -> Option<(&str, &str)> {
-    while !input.is_empty() {
-        let key1 = "<language";
-        let key1_idx = input.find(key1)?;
-        let after_key1 = &input[key1_idx+key1.len()..];
-        let key2 = "</language>";
-        let end_idx = key1_idx + key1.len() + after_key1.find(key2)? + key2.len();
-        return Some((&input[end_idx..].trim_start(), &input[key1_idx..end_idx]));
+    if input.is_empty() {
+        return None;
     }
-    None
+    let key1 = "<language";
+    let key1_idx = input.find(key1)?;
+    let after_key1 = &input[key1_idx+key1.len()..];
+    let key2 = "</language>";
+    let end_idx = key1_idx + key1.len() + after_key1.find(key2)? + key2.len();
+    Some((input[end_idx..].trim_start(), &input[key1_idx..end_idx]))
 }
 fn find_wares_xml_slice (mut input: &str) -> Option<(&str, &str)> {
     while !input.is_empty() {
@@ -169,42 +281,73 @@ fn find_wares_xml_slice (mut input: &str) -> Option<(&str, &str)> {
 //        )
 //}
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
 struct TranslationPos {
     page: String,
     id:   String,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
 pub struct SingleWareProduction {
     pub method:             String,
     pub cicle_seconds:      f64, // found thing which is 1.5 sec
     pub wares_per_cicle:    usize,
     pub wares_dependencies: Vec<(String, usize)>,
+    // (effect type, product): "work" and "sunlight" for now
+    pub effects:            Vec<(String, f64)>,
 
     translation: TranslationPos,
 }
 impl SingleWareProduction {
     pub fn wares_per_minute (&self) -> f64 {
-        self.wares_per_cicle as f64 / (self.cicle_seconds as f64 / 60f64)
+        self.wares_per_cicle as f64 / (self.cicle_seconds / 60f64)
+    }
+    // Effects change only the output, inputs are consumed at the base rate.
+    // `product` is the output bonus at the full effect level: full workforce
+    // gives +product, sunlight scales output relative to 100%.
+    pub fn effects_factor (&self, settings: &PlanSettings) -> f64 {
+        self.effects.iter().fold(
+            1f64,
+            |acc, (effect, product)| match effect.as_str() {
+                "work"     => acc * (1f64 + product * settings.workforce_percent / 100f64),
+                "sunlight" => acc * (1f64 + product * (settings.sunlight_percent / 100f64 - 1f64)),
+                _          => acc,
+            })
+    }
+    pub fn wares_per_minute_with_effects (&self, settings: &PlanSettings) -> f64 {
+        self.wares_per_minute() * self.effects_factor(settings)
     }
     pub fn dependencies_per_minute (&self) -> impl Iterator<Item=(&str, f64)> {
-        self.wares_dependencies.iter().map(|(name, wares_per_cicle)| (name.as_str(), *wares_per_cicle as f64 / (self.cicle_seconds as f64 / 60f64)))
+        self.wares_dependencies.iter().map(|(name, wares_per_cicle)| (name.as_str(), *wares_per_cicle as f64 / (self.cicle_seconds / 60f64)))
     }
-    pub fn fabrics_count_from_desired_wares_per_minute (&self, desired_wares_per_minute: f64) -> usize {
-        (desired_wares_per_minute / self.wares_per_minute()).ceil() as usize
+    pub fn fabrics_count_from_desired_wares_per_minute (&self, desired_wares_per_minute: f64, settings: &PlanSettings) -> usize {
+        (desired_wares_per_minute / self.wares_per_minute_with_effects(settings)).ceil() as usize
     }
 }
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
 pub struct SingleWareInfo {
     pub ware_id:         String,
 
-    // might use in future?
+    pub group:         Option<String>, // "energy", "hightech", ..
+    pub volume:        u32,
     pub price_min:     u32,
+    pub price_average: u32,
     pub price_max:     u32,
     pub transport:     String, // container vs .?.
+    pub tags:          Vec<String>,
+    pub component:     Option<String>, // macro of a module or a ship
 }
-#[derive(Debug, Clone)]
+impl SingleWareInfo {
+    pub fn price (&self, price_point: PricePoint) -> f64 {
+        match price_point {
+            PricePoint::Min => self.price_min as f64,
+            PricePoint::Average => self.price_average as f64,
+            PricePoint::Max => self.price_max as f64,
+            PricePoint::Fraction(fraction) => self.price_min as f64 + fraction * (self.price_max as f64 - self.price_min as f64),
+        }
+    }
+}
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
 pub struct SingleWare {
     pub info: SingleWareInfo,
 
@@ -212,22 +355,51 @@ pub struct SingleWare {
     pub production_methods: Vec<(String, SingleWareProduction)>,
 
     translation: Option<TranslationPos>,
+    description: Option<TranslationPos>,
+    factoryname: Option<TranslationPos>,
 }
 impl SingleWare {
-    fn find_desired_method (&self, prioritylist: &[String], blacklist: &[String]) -> Result<&SingleWareProduction, DataError> {
+    // false if every method is one the player can not build
+    fn is_buildable (&self, unbuildable: &[String]) -> bool {
+        self.production_methods.iter().any(|(name, _)| !unbuildable.contains(name))
+    }
+    fn split_items (&self, ware_methods_opt: Option<&WareMethods>) -> Result<Vec<SplitItem<'_>>, DataError> {
+        ware_methods_opt.map_or(&[][..], |ware_methods| &ware_methods.split).iter()
+            .map(|split| {
+                let production = self.production_methods.iter().find(|(name, _)| name == &split.method).map(|(_, production)| production)
+                                     .ok_or(DataError::UnknownProductionMethod(split.method.clone()))?;
+                Ok(match split.share {
+                       MethodShare::Ratio(ratio) => (production, ratio, 0),
+                       MethodShare::Fabrics(count) => (production, 0f64, count),
+                   })
+            })
+            .collect()
+    }
+    // a module or a ship is built by its own production, the "default" one
+    // if there are several
+    fn build_production (&self) -> Option<&SingleWareProduction> {
+        self.production_methods.iter().find(|(method, _)| method == "default").or(self.production_methods.first()).map(|(_, production)| production)
+    }
+    // the ware's own pin and blacklist go ahead of the global lists
+    fn find_desired_method (&self, prioritylist: &[String], blacklist: &[String], ware_methods_opt: Option<&WareMethods>) -> Result<&SingleWareProduction, DataError> {
         let methods = &self.production_methods;
 
         if methods.is_empty() {
             return Err(DataError::NoProductionMethods(self.info.ware_id.clone()));
         }
 
-        for p in prioritylist {
+        if let Some(WareMethods{method: Some(pinned), ..}) = ware_methods_opt {
+            return methods.iter().find(|(key, _)| key == pinned).map(|(_, method)| method)
+                       .ok_or(DataError::UnknownProductionMethod(pinned.clone()));
+        }
+        let ware_blacklist = ware_methods_opt.map_or(&[][..], |ware_methods| &ware_methods.blacklist);
+        for p in prioritylist.iter().filter(|p| !ware_blacklist.contains(p)) {
             if let Some((_, method)) = methods.iter().find(|(key, _)| key == p) {
                 return Ok(method);
             }
         }
         for (name, method) in methods {
-            if let None = blacklist.iter().find(|&b_item| b_item == name) {
+            if !blacklist.iter().chain(ware_blacklist).any(|b_item| b_item == name) {
                 return Ok(method);
             }
         }
@@ -235,6 +407,54 @@ impl SingleWare {
     }
 }
 
+// `<defaults>` of wares.xml, attributes a ware may omit:
+// ```
+// <defaults id="default" name="default" transport="container" volume="1" tags="container">
+//   <price min="1" average="1" max="1" />
+//   <production time="10" amount="1" method="default" name="{20206,101}">
+// ```
+// A ware without `<production>` still has none, only attributes of the
+// existing ones are inherited.
+#[derive(Debug, Clone, Default, serde::Serialize, serde::Deserialize)]
+struct WareDefaults {
+    ware:       HashMap<String, String>,
+    price:      HashMap<String, String>,
+    production: HashMap<String, String>,
+}
+impl WareDefaults {
+    fn load (node: roxmltree::Node) -> Self {
+        let attributes = |node: Option<roxmltree::Node>| node.map_or(HashMap::new(), |node| node.attributes().map(|atr| (atr.name().to_string(), atr.value().to_string())).collect());
+        Self {
+            // not inherited, the own id and translation of the block
+            ware:       attributes(Some(node)).into_iter().filter(|(key, _)| key != "id" && key != "name").collect(),
+            price:      attributes(node.children().find(|n| n.has_tag_name("price"))),
+            production: attributes(node.children().find(|n| n.has_tag_name("production"))),
+        }
+    }
+}
+
+// Declared at the top of wares.xml:
+// ```
+// <production>
+//   <method id="teladi" name="{20206,401}">
+//     <default race="teladi" />
+//   </method>
+//   <method id="xenon" name="{20206,601}" tags="noplayerbuild">
+// ```
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub struct ProductionMethod {
+    pub id:            String,
+    pub tags:          Vec<String>,
+    pub default_races: Vec<String>,
+
+    translation: Option<TranslationPos>,
+}
+impl ProductionMethod {
+    pub fn is_player_buildable (&self) -> bool {
+        !self.tags.iter().any(|tag| tag == "noplayerbuild")
+    }
+}
+
 // Iterator over {xml-comment, xml-node}, all other ignored
 struct CommentTagIter<'a, 'input: 'a, T: Iterator<Item=roxmltree::Node<'a, 'input>>> {
     neighbours: T,
@@ -289,13 +509,13 @@ fn try_name_to_unicode_id (input: String) -> String {
 fn collect_lang_from_pairs<'a, 'input: 'a, It: Iterator<Item=roxmltree::Node<'a, 'input>>> (neighbours: It) -> Result<Vec<(String, String)>, DataError> {
     // adapted for (2023-05 version)
     // ```
-    // <languages>
-    //   <!-- English -->
-    //   <language id="44" name="English" voice="true" warning="A restart is required for changes to take effect!" />
-    //   <!-- German -->
-    //   <language id="49" name="Deutsch" voice="true" warning="Damit die ц└nderung wirksam wird, ist ein Neustart notwendig!" />
-    //   <!-- French -->
-    //   <language id="33" name="Franц╖ais" voice="true" warning="Un redц╘marrage est nц╘cessaire pour la prise en compte des changements !" />
+    // <languages>
+    //   <!-- English -->
+    //   <language id="44" name="English" voice="true" warning="A restart is required for changes to take effect!" />
+    //   <!-- German -->
+    //   <language id="49" name="Deutsch" voice="true" warning="Damit die ц└nderung wirksam wird, ist ein Neustart notwendig!" />
+    //   <!-- French -->
+    //   <language id="33" name="Franц╖ais" voice="true" warning="Un redц╘marrage est nц╘cessaire pour la prise en compte des changements !" />
     // </languages>
     // ```
     // As you can see, comments are reliable, names ("Franц╖ais") are not
@@ -303,7 +523,7 @@ fn collect_lang_from_pairs<'a, 'input: 'a, It: Iterator<Item=roxmltree::Node<'a,
     //let mut overwritten_warnings = Vec::new();
     for (comment, node) in (CommentTagIter {neighbours}).filter(|(_, node)| node.tag_name().name() == "language") {
         let key = try_name_to_unicode_id(comment.text().unwrap().trim().into());
-        if let Some(_) = result.iter().find_map(|(existing_key, existing_value)| (existing_key.as_str() == key).then_some(existing_value)) {
+        if result.iter().any(|(existing_key, _)| existing_key.as_str() == key) {
             if key != "ch" {
                 // I have no idea how to handle Chinese & Simplified Chinese
                 //overwritten_warnings.push((key.clone(), existing_value.clone()));
@@ -360,20 +580,190 @@ fn find_langs (input: &str, dbg_path: &std::path::Path) -> Result<Vec<(String, S
     Ok(result)
 }
 
-#[derive(Debug, Clone)]
+// Virtual paths of the macros by lowercase name, from index/macros.xml:
+// ```
+// <entry name="prod_gen_microchips_macro" value="assets\structures\production\macros\prod_gen_microchips_macro" />
+// ```
+// Extensions add their macros by a diff, with paths "extensions\<id>\..."
+fn load_macros_index<'a> (files: &'a GameFiles, extensions: &'a [(String, GameFiles)]) -> Result<HashMap<String, (&'a GameFiles, String)>, DataError> {
+    let mut result = HashMap::new();
+    for index_files in std::iter::once(files).chain(extensions.iter().map(|(_, files)| files)).filter(|files| files.contains(MACROS_INDEX_PATH)) {
+        let (content, dbg_path) = load_game_string_file(index_files, MACROS_INDEX_PATH)?;
+        let mut origin = read_xml(content, find_whole_xml_slice, "index", &dbg_path)?;
+        let (_, doc) = origin.get_mut();
+        for node in doc[0].descendants().filter(|n| n.has_tag_name("entry")) {
+            let name = mygetatr(node, "name")?.to_ascii_lowercase();
+            let path = format!("{}.xml", mygetatr(node, "value")?.replace('\\', "/"));
+            let entry
+                = match path.strip_prefix(EXTENSIONS_DIR).and_then(|rest| rest.strip_prefix('/')).and_then(|rest| rest.split_once('/')) {
+                      Some((extension_id, extension_path)) => match extensions.iter().find(|(id, _)| id.eq_ignore_ascii_case(extension_id)) {
+                          Some((_, extension_files)) => (extension_files, extension_path.to_string()),
+                          // the extension is disabled
+                          None => continue,
+                      },
+                      None => (files, path),
+                  };
+            result.insert(name, entry);
+        }
+    }
+    Ok(result)
+}
+
+// Macro of a module ware, its `<component ref>`:
+// ```
+// <macro name="prod_gen_microchips_macro" class="production">
+//   <properties>
+//     <identification name="{20104,24701}" makerrace="argon" />
+//     <production wares="microchips">
+//       <queue ware="microchips" method="default" />
+//     </production>
+//     <workforce max="90" />
+// ```
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub struct ProductionModule {
+    pub module_ware: String,
+    pub macro_name:  String,
+    pub race:        Option<String>,
+    // (ware_id, method), the method is "default" unless the queue names it
+    pub productions: Vec<(String, String)>,
+    pub workforce:   Option<u32>,
+}
+impl ProductionModule {
+    // none if the macro makes no wares
+    fn load_from_node (parent: roxmltree::Node<'_, 'static>, module_ware: &str, macro_name: &str) -> Result<Option<Self>, DataError> {
+        let macro_node = parent.descendants().find(|n| n.has_tag_name("macro") && n.attribute("name").is_some_and(|name| name.eq_ignore_ascii_case(macro_name)));
+        let properties
+            = match macro_node.and_then(|n| n.children().find(|n| n.has_tag_name("properties"))) {
+                  Some(properties) => properties,
+                  None => return Ok(None),
+              };
+        let production
+            = match properties.children().find(|n| n.has_tag_name("production")) {
+                  Some(production) => production,
+                  None => return Ok(None),
+              };
+        let mut productions
+            = production.descendants().filter(|n| n.has_tag_name("queue") && n.has_attribute("ware"))
+              .map(|n| (n.attribute("ware").unwrap().to_string(), n.attribute("method").unwrap_or("default").to_string()))
+              .collect::<Vec<_>>();
+        if productions.is_empty() {
+            productions = mygetatr(production, "wares")?.split_whitespace().map(|ware| (ware.to_string(), "default".to_string())).collect();
+        }
+        let workforce
+            = match properties.children().find(|n| n.has_tag_name("workforce")).filter(|n| n.has_attribute("max")) {
+                  Some(workforce) => Some(mygetatrparsed(workforce, "max")?),
+                  None => None,
+              };
+        Ok(Some(Self {
+            module_ware: module_ware.to_string(),
+            macro_name:  macro_name.to_string(),
+            race:        properties.children().find(|n| n.has_tag_name("identification")).and_then(|n| n.attribute("makerrace")).map(|race| race.to_string()),
+            productions,
+            workforce,
+        }))
+    }
+}
+
+// Macro of a storage module ware:
+// ```
+// <macro name="storage_arg_l_container_01_macro" class="storage">
+//   <properties>
+//     <identification name="{20104,11001}" makerrace="argon" />
+//     <cargo max="1000000" tags="container" />
+// ```
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub struct StorageModule {
+    pub module_ware: String,
+    pub macro_name:  String,
+    // transports the cargo takes, "container", "solid", "liquid"
+    pub transports:  Vec<String>,
+    pub capacity:    u64,
+}
+impl StorageModule {
+    // none if the macro has no cargo
+    fn load_from_node (parent: roxmltree::Node<'_, 'static>, module_ware: &str, macro_name: &str) -> Result<Option<Self>, DataError> {
+        let cargo
+            = match parent.descendants()
+                  .find(|n| n.has_tag_name("macro") && n.attribute("name").is_some_and(|name| name.eq_ignore_ascii_case(macro_name)))
+                  .and_then(|n| n.children().find(|n| n.has_tag_name("properties")))
+                  .and_then(|n| n.children().find(|n| n.has_tag_name("cargo"))) {
+                  Some(cargo) => cargo,
+                  None => return Ok(None),
+              };
+        Ok(Some(Self {
+            module_ware: module_ware.to_string(),
+            macro_name:  macro_name.to_string(),
+            transports:  mygetatr(cargo, "tags")?.split_whitespace().map(|tag| tag.to_string()).collect(),
+            capacity:    mygetatrparsed(cargo, "max")?,
+        }))
+    }
+}
+
+// Macro of a habitat module ware:
+// ```
+// <macro name="hab_arg_m_01_macro" class="habitation">
+//   <properties>
+//     <identification name="{20104,31201}" makerrace="argon" />
+//     <workforce capacity="250" race="argon" />
+// ```
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub struct HabitatModule {
+    pub module_ware: String,
+    pub macro_name:  String,
+    pub race:        Option<String>,
+    // workers it houses
+    pub capacity:    u32,
+}
+impl HabitatModule {
+    // none if the macro houses no workers
+    fn load_from_node (parent: roxmltree::Node<'_, 'static>, module_ware: &str, macro_name: &str) -> Result<Option<Self>, DataError> {
+        let workforce
+            = match parent.descendants()
+                  .find(|n| n.has_tag_name("macro") && n.attribute("name").is_some_and(|name| name.eq_ignore_ascii_case(macro_name)))
+                  .and_then(|n| n.children().find(|n| n.has_tag_name("properties")))
+                  .and_then(|n| n.children().find(|n| n.has_tag_name("workforce") && n.has_attribute("capacity"))) {
+                  Some(workforce) => workforce,
+                  None => return Ok(None),
+              };
+        Ok(Some(Self {
+            module_ware: module_ware.to_string(),
+            macro_name:  macro_name.to_string(),
+            race:        workforce.attribute("race").map(|race| race.to_string()),
+            capacity:    mygetatrparsed(workforce, "capacity")?,
+        }))
+    }
+}
+
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
 struct Wares {
     id_to_dsc: Vec<(String, SingleWare)>,
+    // in the declaration order
+    methods:   Vec<ProductionMethod>,
+    defaults:  WareDefaults,
+    // by the macros of module wares, empty if loaded from strings
+    modules:   Vec<ProductionModule>,
+    storages:  Vec<StorageModule>,
+    habitats:  Vec<HabitatModule>,
 }
 impl Wares {
     fn get (&self, the_key: &str) -> Result<&SingleWare, DataError> {
         self.id_to_dsc.iter().find_map(|(key, value)| if key.as_str() == the_key {Some(value)} else {None}).ok_or(DataError::UnknownWare(the_key.to_string()))
     }
     fn read_translation<'a> (node: roxmltree::Node<'a, 'static>, is_required: bool) -> Result<Option<TranslationPos>, DataError> {
+        Self::read_translation_atr(node, "name", is_required)
+    }
+    fn read_translation_atr<'a> (node: roxmltree::Node<'a, 'static>, atr_key: &'static str, is_required: bool) -> Result<Option<TranslationPos>, DataError> {
+        Self::parse_translation(node, atr_key, node.attribute(atr_key), is_required)
+    }
+    fn parse_translation<'a> (node: roxmltree::Node<'a, 'static>, atr_key: &'static str, content_opt: Option<&str>, is_required: bool) -> Result<Option<TranslationPos>, DataError> {
         lazy_static::lazy_static! {
             static ref RE: regex::Regex = regex::Regex::new(r"^\{(?P<page>[[:digit:]]+),[[:space:]]*(?P<itemid>[[:digit:]]+)}$").unwrap();
         }
-        let atr_key = "name";
-        let content = node.attribute(atr_key).ok_or(DataError::AttributeNotFound(atr_key, format!("{:?}", node)))?.trim();
+        let content = match content_opt {
+                          Some(content) => content.trim(),
+                          None if is_required => return Err(DataError::AttributeNotFound(atr_key, format!("{:?}", node))),
+                          None => return Ok(None),
+                      };
         //let capture = RE.captures(content).ok_or(DataError::AttributeBadValue(atr_key, format!("{:?}", node), "{pagenum:stringid}, e.g. {20201,402}"))?;
         let capture
             = match RE.captures(content) {
@@ -386,36 +776,84 @@ impl Wares {
         let id   = capture.name("itemid").unwrap().as_str().into();
         Ok(Some(TranslationPos{page, id}))
     }
-    fn load_from_xml (xml: &roxmltree::Document<'static>) -> Result<Self, DataError> {
+    fn load_from_xml (xml: &roxmltree::Document<'static>, inherited: &WareDefaults) -> Result<Self, DataError> {
+        Self::load_from_node(xml.root_element(), inherited)
+    }
+    fn merge (&mut self, other: Wares) {
+        for method in other.methods {
+            match self.methods.iter_mut().find(|existing| existing.id == method.id) {
+                Some(existing) => *existing = method,
+                None => self.methods.push(method),
+            }
+        }
+        for (key, ware) in other.id_to_dsc {
+            match self.id_to_dsc.iter_mut().find(|(existing_key, _)| existing_key == &key) {
+                Some((_, existing)) => *existing = ware,
+                None => self.id_to_dsc.push((key, ware)),
+            }
+        }
+    }
+    fn load_methods (parent: roxmltree::Node<'_, 'static>) -> Result<Vec<ProductionMethod>, DataError> {
+        let mut methods: Vec<ProductionMethod> = Vec::new();
+        for node in parent.children().filter(|n| n.has_tag_name("production")).flat_map(|n| n.children().filter(|n| n.has_tag_name("method"))) {
+            let id = mygetatr(node, "id")?.to_string();
+            if methods.iter().any(|method| method.id == id) {
+                return Err(DataError::DuplicateValue(id, format!("{:?}", node)));
+            }
+            let tags = node.attribute("tags").map_or(Vec::new(), |tags| tags.split_whitespace().map(|tag| tag.to_string()).collect());
+            let default_races = node.children().filter(|n| n.has_tag_name("default")).map(|n| mygetatr(n, "race").map(|race| race.to_string())).collect::<Result<Vec<_>, _>>()?;
+            let translation = Self::read_translation(node, false)?;
+            methods.push(ProductionMethod{id, tags, default_races, translation});
+        }
+        Ok(methods)
+    }
+    // `inherited` are the defaults of the base wares.xml, for extensions
+    // bringing own <wares> without <defaults>
+    fn load_from_node (parent: roxmltree::Node<'_, 'static>, inherited: &WareDefaults) -> Result<Self, DataError> {
+        let mut methods = Self::load_methods(parent)?;
+        let defaults = parent.children().find(|n| n.has_tag_name("defaults")).map_or(inherited.clone(), WareDefaults::load);
         let mut id_to_dsc = Vec::new();
-        for node in xml.root_element().children().filter(|n| n.has_tag_name("ware")) {
+        for node in parent.children().filter(|n| n.has_tag_name("ware")) {
             let ware_id_key = "id";
             let ware_id = mygetatr(node, ware_id_key)?.to_string();
-            let transport = mygetatr(node, "transport")?.to_string();
-            let (price_min, price_max) = {
-                let price_node = myfindchildtag(node, "price")?;
-                ( mygetatrparsed(price_node, "min")?,
-                  mygetatrparsed(price_node, "max")? )
+            let transport = mygetatrinherited(node, &defaults.ware, "transport")?.to_string();
+            let group = mygetatrinherited(node, &defaults.ware, "group").ok().map(|group| group.to_string());
+            let volume = mygetatrinheritedparsed(node, &defaults.ware, "volume")?;
+            let tags = mygetatrinherited(node, &defaults.ware, "tags").map_or(Vec::new(), |tags| tags.split_whitespace().map(|tag| tag.to_string()).collect());
+            let (price_min, price_average, price_max) = {
+                // the whole <price> may be left out as well, the ware node
+                // has no price attributes so all of them come from defaults
+                let price_node = node.children().find(|n| n.has_tag_name("price")).unwrap_or(node);
+                ( mygetatrinheritedparsed(price_node, &defaults.price, "min")?,
+                  mygetatrinheritedparsed(price_node, &defaults.price, "average")?,
+                  mygetatrinheritedparsed(price_node, &defaults.price, "max")? )
             };
             let ware_id_key = ware_id.clone();
             let info = SingleWareInfo {
                            ware_id,
+                           group,
+                           volume,
                            price_min,
+                           price_average,
                            price_max,
                            transport,
+                           tags,
+                           component: node.children().find(|n| n.has_tag_name("component")).and_then(|n| n.attribute("ref")).map(|component| component.to_string()),
                            };
             let translation = Self::read_translation(node, false)?;
+            let description = Self::read_translation_atr(node, "description", false)?;
+            let factoryname = Self::read_translation_atr(node, "factoryname", false)?;
             let mut production_methods = Vec::new();
             for prod_node in node.children().filter(|n| n.has_tag_name("production")) {
-                let method = mygetatr(prod_node, "method")?.to_string();
+                let method = mygetatrinherited(prod_node, &defaults.production, "method")?.to_string();
                 if production_methods.iter().find(|(m,_)| m == &method).is_some() {
                     return Err(DataError::DuplicateValue(
                                method,
                                format!("{:?}", node))
                                );
                 }
-                let cicle_seconds = mygetatrparsed(prod_node, "time")?;
-                let wares_per_cicle = mygetatrparsed(prod_node, "amount")?;
+                let cicle_seconds = mygetatrinheritedparsed(prod_node, &defaults.production, "time")?;
+                let wares_per_cicle = mygetatrinheritedparsed(prod_node, &defaults.production, "amount")?;
 
                 let wares_dependencies
                     = 'wb: {
@@ -463,13 +901,21 @@ impl Wares {
                 //              acc.push((key, mygetatrparsed(node, "amount")?));
                 //              Ok(acc)
                 //          })?;
+                let effects
+                    = match myfindchildtag(prod_node, "effects") {
+                          Ok(effects_node) => effects_node.children().filter(|n| n.has_tag_name("effect"))
+                                              .map(|n| Ok((mygetatr(n, "type")?.to_string(), mygetatrparsed(n, "product")?)))
+                                              .collect::<Result<Vec<_>, DataError>>()?,
+                          Err(_) => Vec::new(),
+                      };
                 let new_key = method.clone();
-                let translation = Self::read_translation(prod_node, true)?.unwrap();
+                let translation = Self::parse_translation(prod_node, "name", mygetatrinherited(prod_node, &defaults.production, "name").ok(), true)?.unwrap();
                 let new_value = SingleWareProduction {
                                     method,
                                     cicle_seconds,
                                     wares_per_cicle,
                                     wares_dependencies,
+                                    effects,
                                     translation,
                                     };
                 production_methods.push((new_key, new_value));
@@ -478,10 +924,18 @@ impl Wares {
                                info,
                                production_methods,
                                translation,
+                               description,
+                               factoryname,
                                };
+            // not every wares.xml declares the methods it uses (extensions)
+            for (method, _) in new_ware.production_methods.iter() {
+                if !methods.iter().any(|existing| &existing.id == method) {
+                    methods.push(ProductionMethod{id: method.clone(), tags: Vec::new(), default_races: Vec::new(), translation: None});
+                }
+            }
             id_to_dsc.push((ware_id_key, new_ware));
         }
-        Ok(Wares{id_to_dsc})
+        Ok(Wares{id_to_dsc, methods, defaults, modules: Vec::new(), storages: Vec::new(), habitats: Vec::new()})
     }
     //fn gen_production_methods_list (&self) -> Vec<String> {
     //    // https://qna.habr.com/q/1289244
@@ -508,57 +962,291 @@ impl Wares {
         // now trying extract lang code maps
         let (string, doc) = origin.get_mut();
         let translations_ids = find_langs(string.as_str(), &dbg_path)?;
-        let me = Self::load_from_xml(&doc[0])?;
+        let me = Self::load_from_xml(&doc[0], &WareDefaults::default())?;
         //let production_methods = me.gen_production_methods_list();
 
         Ok(( me,
              translations_ids, ))
     }
-    fn load_wares_translationids_and_productionmethods (gamedir: &std::path::Path) -> Result<(Self, Vec<(String, String)>), DataError> {
-        let (content, dbg_path) = load_string_file(gamedir, PRODUCTION_FILE)?;
-        Self::load_wares_translationids_and_productionmethods_from_string(content, dbg_path)
+    fn load_wares_translationids_and_productionmethods (files: &GameFiles, extensions: &[(String, GameFiles)]) -> Result<(Self, Vec<(String, String)>), DataError> {
+        let (languages, dbg_path) = load_game_string_file(files, LANGUAGES_PATH)?;
+        let translations_ids = find_langs(&languages, &dbg_path)?;
+
+        let (content, dbg_path) = load_game_string_file(files, WARES_PATH)?;
+        let mut origin = read_xml(content, find_whole_xml_slice, "wares", &dbg_path)?;
+
+        // extensions either patch wares.xml with <diff> or bring own <wares>
+        let mut extension_origins = Vec::new();
+        for (_, extension_files) in extensions.iter().filter(|(_, files)| files.contains(WARES_PATH)) {
+            let (content, dbg_path) = load_game_string_file(extension_files, WARES_PATH)?;
+            extension_origins.push((read_xml(content, find_whole_xml_slice, "wares", &dbg_path)?, dbg_path));
+        }
+        let (diffs, extension_docs): (Vec<_>, Vec<_>)
+            = extension_origins.iter_mut()
+              .map(|(origin, dbg_path)| (&origin.get_mut().1[0], dbg_path.as_path()))
+              .partition(|(doc, _)| doc.root_element().has_tag_name("diff"));
+
+        let (_, doc) = origin.get_mut();
+        let mut me
+            = if diffs.is_empty() {
+                  Self::load_from_xml(&doc[0], &WareDefaults::default())?
+              }
+              else {
+                  let patched = crate::xmldiff::apply_diffs(&doc[0], &diffs.iter().map(|(diff, _)| *diff).collect::<Vec<_>>())
+                                    .map_err(|(idx, e)| DataError::XmlDiffError(diffs[idx].1.to_path_buf(), e))?;
+                  let mut patched_origin = read_xml(patched, find_whole_xml_slice, "wares", &dbg_path)?;
+                  let (_, patched_doc) = patched_origin.get_mut();
+                  Self::load_from_xml(&patched_doc[0], &WareDefaults::default())?
+              };
+        for (extension_doc, _) in extension_docs {
+            let extension_wares = Self::load_from_xml(extension_doc, &me.defaults)?;
+            me.merge(extension_wares);
+        }
+
+        Ok(( me,
+             translations_ids, ))
     }
     fn gen_production_methods_list (&self) -> Vec<String> {
-        let all_methods
-            = self.id_to_dsc.iter()
-                  .map(|(_, ware)| ware.production_methods.iter()
-                       .map(|(_, production_method)|
-                            ( &production_method
-                                   .method,
-                               production_method
-                                   .translation.id.parse::<u32>().unwrap())
-                            )
-                       ).flatten()
-                  .fold(
-                      HashMap::new(),
-                      |mut acc, (name, translation_id)| {
-                          if !acc.contains_key(name) {
-                              acc.insert(name.clone(), translation_id);
-                          }
-                          acc
-                      }
-                      );
-        let mut all_methods = Vec::from_iter(all_methods.into_iter());
-        all_methods.sort_by_key(|(_, translation_id)| *translation_id);
-        let result = all_methods.iter().map(|(method, _)| method.clone()).collect();
-        result
+        self.methods.iter().map(|method| method.id.clone()).collect()
+    }
+    // Production, storage and habitat macros of the module wares, a production module
+    // whose macro is not found is left to the naming convention
+    fn load_module_macros (&mut self, files: &GameFiles, extensions: &[(String, GameFiles)]) -> Result<(), DataError> {
+        let index = load_macros_index(files, extensions)?;
+        let mut modules = Vec::new();
+        let mut storages = Vec::new();
+        let mut habitats = Vec::new();
+        for (ware_id, ware) in self.id_to_dsc.iter().filter(|(_, ware)| ware.info.tags.iter().any(|tag| tag == "module")) {
+            let macro_name
+                = match ware.info.component.as_ref() {
+                      Some(macro_name) => macro_name,
+                      None => continue,
+                  };
+            let (macro_files, path)
+                = match index.get(&macro_name.to_ascii_lowercase()) {
+                      Some((macro_files, path)) => (*macro_files, path.clone()),
+                      None => match [PRODUCTION_MACROS_DIR, STORAGE_MACROS_DIR, HABITAT_MACROS_DIR].iter().map(|dir| format!("{}/{}.xml", dir, macro_name)).find(|path| files.contains(path)) {
+                          Some(path) => (files, path),
+                          None => continue,
+                      },
+                  };
+            if !macro_files.contains(&path) {
+                continue;
+            }
+            let (content, dbg_path) = load_game_string_file(macro_files, &path)?;
+            let mut origin = read_xml(content, find_whole_xml_slice, "macros", &dbg_path)?;
+            let (_, doc) = origin.get_mut();
+            if let Some(module) = ProductionModule::load_from_node(doc[0].root_element(), ware_id, macro_name)? {
+                modules.push(module);
+            }
+            if let Some(storage) = StorageModule::load_from_node(doc[0].root_element(), ware_id, macro_name)? {
+                storages.push(storage);
+            }
+            if let Some(habitat) = HabitatModule::load_from_node(doc[0].root_element(), ware_id, macro_name)? {
+                habitats.push(habitat);
+            }
+        }
+        self.modules = modules;
+        self.storages = storages;
+        self.habitats = habitats;
+        Ok(())
+    }
+    // The module ware whose macro makes the ware by the method. Without
+    // macros production modules are named by convention
+    // `module_<race>_prod_<ware>_01`, "gen" for methods with no race
+    fn find_production_module (&self, ware_id: &str, method: &str) -> Option<&SingleWare> {
+        if let Some(module) = self.modules.iter().find(|module| module.productions.iter().any(|(ware, other)| ware == ware_id && other == method)) {
+            return self.get(&module.module_ware).ok();
+        }
+        let races = self.methods.iter().find(|existing| existing.id == method).map_or(Vec::new(), |method| method.default_races.clone());
+        races.iter().map(|race| race.chars().take(3).collect::<String>()).chain(std::iter::once("gen".to_string()))
+            .find_map(|race| self.get(&format!("module_{}_prod_{}_01", race, ware_id)).ok())
+    }
+    // Workers of a module making the ware by the method, its `workforce max` at
+    // `workforce_percent`, zero without `PlanSettings::workforce` or the macro.
+    fn module_workers (&self, ware_id: &str, method: &str, settings: &PlanSettings) -> f64 {
+        if settings.workforce.is_none() {
+            return 0f64;
+        }
+        self.modules.iter()
+            .find(|module| module.productions.iter().any(|(ware, other)| ware == ware_id && other == method))
+            .and_then(|module| module.workforce)
+            .map_or(0f64, |workforce| workforce as f64 * settings.workforce_percent / 100f64)
+    }
+    // The habitat of `Workforce::habitat`, the one housing the most workers if
+    // none
+    fn find_habitat_module (&self, workforce: &Workforce) -> Result<&HabitatModule, DataError> {
+        match workforce.habitat.as_ref() {
+            Some(habitat) => self.habitats.iter().find(|module| &module.module_ware == habitat),
+            None => self.habitats.iter().max_by_key(|module| module.capacity),
+        }
+        .filter(|module| 0 != module.capacity)
+        .ok_or(DataError::HabitatNotFound(workforce.habitat.clone()))
+    }
+    // the maker race of the module macro, the race of the method otherwise
+    fn module_race (&self, module_ware_id: &str, method: &str) -> Option<String> {
+        match self.modules.iter().find(|module| module.module_ware == module_ware_id) {
+            Some(module) => module.race.clone(),
+            None => self.methods.iter().find(|existing| existing.id == method).and_then(|method| method.default_races.first().cloned()),
+        }
+    }
+    // methods the planner must not pick unless explicitly prioritized
+    fn gen_unbuildable_methods_list (&self, prioritylist: &[String]) -> Vec<String> {
+        self.methods.iter()
+            .filter(|method| !method.is_player_buildable() && !prioritylist.contains(&method.id))
+            .map(|method| method.id.clone())
+            .collect()
+    }
+}
+// (ware_id, wares_per_minute, info, (fabrics_count, production) per method, empty if imported)
+type FabricsAccItem<'a> = (String, f64, &'a SingleWareInfo, Vec<(usize, &'a SingleWareProduction)>);
+// (production, ratio of the wares not made by fixed modules, fixed module count)
+type SplitItem<'a> = (&'a SingleWareProduction, f64, usize);
+
+// Module count of every method, a method listed twice gets the sum
+fn split_module_counts<'a> (items: &[SplitItem<'a>], wares_per_minute: f64, settings: &PlanSettings) -> Vec<(usize, &'a SingleWareProduction)> {
+    let fixed = items.iter().map(|(production, _, count)| production.wares_per_minute_with_effects(settings) * *count as f64).sum::<f64>();
+    let rest = (wares_per_minute - fixed).max(0.);
+    let ratios = items.iter().map(|(_, ratio, _)| ratio).sum::<f64>();
+
+    let mut result: Vec<(usize, &SingleWareProduction)> = Vec::new();
+    for (production, ratio, count) in items {
+        let count
+            = if 0. < *ratio {
+                  count + production.fabrics_count_from_desired_wares_per_minute(rest * ratio / ratios, settings)
+              }
+              else {
+                  *count
+              };
+        match result.iter_mut().find(|(_, other)| other.method == production.method) {
+            Some((acc_count, _)) => *acc_count += count,
+            None => result.push((count, production)),
+        }
     }
+    result
+}
+
+const WORKUNIT_WARE_ID: &str = "workunit_busy";
+
+// What one worker consumes per minute, empty without
+// `PlanSettings::workforce`. A cycle of `workunit_busy` feeds its `amount` of
+// workers.
+fn worker_consumption<'a> (wares: &'a Wares, settings: &PlanSettings, prioritylist: &[String], blacklist: &[String]) -> Result<Vec<(&'a str, f64)>, DataError> {
+    let workforce
+        = match settings.workforce.as_ref() {
+              Some(workforce) => workforce,
+              None => return Ok(Vec::new()),
+          };
+    let ware = wares.get(WORKUNIT_WARE_ID)?;
+    let ware_production
+        = match workforce.method.as_ref() {
+              Some(method) => ware.production_methods.iter().find(|(name, _)| name == method).map(|(_, production)| production)
+                                  .ok_or(DataError::UnknownProductionMethod(method.clone()))?,
+              None => ware.find_desired_method(prioritylist, blacklist, settings.ware_methods(WORKUNIT_WARE_ID))?,
+          };
+    Ok(ware_production.dependencies_per_minute()
+           .map(|(dependency, wares_per_minute)| (dependency, wares_per_minute / ware_production.wares_per_cicle as f64))
+           .collect())
 }
+// What the workers of one module making the ware by the method consume per
+// minute
+fn module_workers_consumption<'a> (wares: &Wares, worker_consumption: &[(&'a str, f64)], ware_id: &str, method: &str, settings: &PlanSettings) -> Vec<(&'a str, f64)> {
+    let workers = wares.module_workers(ware_id, method, settings);
+    worker_consumption.iter().map(|(dependency, wares_per_minute)| (*dependency, wares_per_minute * workers)).filter(|(_, wares_per_minute)| 0. < *wares_per_minute).collect()
+}
+
+// What building one module of (ware, method) takes per minute within
+// `PlanSettings::build_hours`, empty without them
+fn module_build_consumption<'a> (wares: &'a Wares, ware_id: &str, method: &str, settings: &PlanSettings) -> Result<Vec<(&'a str, f64)>, DataError> {
+    let build_hours
+        = match settings.build_hours {
+              Some(build_hours) => build_hours,
+              None => return Ok(Vec::new()),
+          };
+    let module = wares.find_production_module(ware_id, method).ok_or(DataError::ModuleNotFound(ware_id.to_string(), method.to_string()))?;
+    Ok(build_consumption(module, build_hours))
+}
+// What building one module ware takes per minute within `build_hours`
+fn build_consumption (module: &SingleWare, build_hours: f64) -> Vec<(&str, f64)> {
+    module.build_production().into_iter()
+        .flat_map(|production| production.wares_dependencies.iter().map(move |(dependency, amount)| (dependency.as_str(), *amount as f64 / production.wares_per_cicle as f64 / (60f64 * build_hours))))
+        .collect()
+}
+// (habitat module, what building one takes per minute)
+type HabitatBuild<'a> = (&'a HabitatModule, Vec<(&'a str, f64)>);
+
+// The habitat module housing the workers and what building one takes per
+// minute within `PlanSettings::build_hours`, none without a workforce
+fn habitat_build_consumption<'a> (wares: &'a Wares, settings: &PlanSettings) -> Result<Option<HabitatBuild<'a>>, DataError> {
+    let workforce
+        = match settings.workforce.as_ref() {
+              Some(workforce) => workforce,
+              None => return Ok(None),
+          };
+    let habitat = wares.find_habitat_module(workforce)?;
+    let consumption
+        = match settings.build_hours {
+              Some(build_hours) => build_consumption(wares.get(&habitat.module_ware)?, build_hours),
+              None => Vec::new(),
+          };
+    Ok(Some((habitat, consumption)))
+}
+
 #[derive(Debug, Clone)]
 struct Fabrics<'a> {
-    wares: &'a Wares,
-    acc:   Vec<(String, f64, &'a SingleWareInfo, Option<(usize, &'a SingleWareProduction)>)>,
+    wares:       &'a Wares,
+    settings:    &'a PlanSettings,
+    unbuildable: &'a [String],
+    acc:         Vec<FabricsAccItem<'a>>,
+    // wares whose dependencies are being added, to catch recipe cycles
+    path:        Vec<String>,
 }
 impl<'a> Fabrics<'a> {
-    fn into_acc (self) -> Vec<(String, f64, &'a SingleWareInfo, Option<(usize, &'a SingleWareProduction)>)> {
+    // a cycle that still adds modules after that many passes won't converge
+    const MAX_CYCLE_PASSES: usize = 100;
+    // a cycle needing more modules of a ware than that is taken as diverging
+    const MAX_CYCLE_MODULES: usize = 1_000_000;
+
+    fn into_acc (self) -> Vec<FabricsAccItem<'a>> {
         self.acc
     }
-    fn new (wares: &'a Wares) -> Self {
-        Self {wares, acc: Vec::new()}
+    fn new (wares: &'a Wares, settings: &'a PlanSettings, unbuildable: &'a [String]) -> Self {
+        Self {wares, settings, unbuildable, acc: Vec::new(), path: Vec::new()}
     }
-    fn find (&mut self, ware_id: &str) -> Option<&mut (String, f64, &'a SingleWareInfo, Option<(usize, &'a SingleWareProduction)>)> {
+    fn find (&mut self, ware_id: &str) -> Option<&mut FabricsAccItem<'a>> {
         self.acc.iter_mut().find(|(id, ..)| id == ware_id)
     }
+    // none if imported, existing modules fix the method of their ware
+    fn pick_method (&self, ware: &'a SingleWare, prioritylist: &[String], blacklist: &[String]) -> Result<Option<&'a SingleWareProduction>, DataError> {
+        if self.settings.is_imported(&ware.info.ware_id) {
+            return Ok(None);
+        }
+        if let Some(existing) = self.settings.existing(&ware.info.ware_id) {
+            return ware.production_methods.iter().find(|(name, _)| name == &existing.method).map(|(_, method)| Some(method))
+                       .ok_or(DataError::UnknownProductionMethod(existing.method.clone()));
+        }
+        let ware_methods_opt = self.settings.ware_methods(&ware.info.ware_id);
+        if ware.is_buildable(self.unbuildable) || ware_methods_opt.is_some_and(|ware_methods| ware_methods.method.is_some()) {
+            Ok(Some(ware.find_desired_method(prioritylist, blacklist, ware_methods_opt)?))
+        }
+        else {
+            Ok(None)
+        }
+    }
+    // empty if imported, what the ratios of a split don't take goes to the
+    // usual method
+    fn pick_methods (&self, ware: &'a SingleWare, prioritylist: &[String], blacklist: &[String]) -> Result<Vec<SplitItem<'a>>, DataError> {
+        if self.settings.is_imported(&ware.info.ware_id) {
+            return Ok(Vec::new());
+        }
+        let mut result = ware.split_items(self.settings.ware_methods(&ware.info.ware_id))?;
+        if !result.iter().any(|(_, ratio, _)| 0. < *ratio) {
+            if let Some(ware_production) = self.pick_method(ware, prioritylist, blacklist)? {
+                result.push((ware_production, 1f64, 0));
+            }
+        }
+        Ok(result)
+    }
     fn add_wares_rec (&mut self, ware_id: &str, wares_per_minute: f64, prioritylist: &[String], blacklist: &[String]) -> Result<(), DataError> {
         if 0. == wares_per_minute {
             return Ok(())
@@ -566,61 +1254,363 @@ impl<'a> Fabrics<'a> {
         else if 0. > wares_per_minute {
             panic!("For ware_id \"{}\" got request for negative \"{}\" wares per minute count!", ware_id, wares_per_minute);
         }
-        let count_added_opt
-            = match self.find(ware_id) {
-                  Some((_, acc_wares_per_minute, _, Some((acc_count, ware_production)))) => {
-                      *acc_wares_per_minute += wares_per_minute;
-                      let prev_value         = *acc_count;
-                      *acc_count             = ware_production.fabrics_count_from_desired_wares_per_minute(*acc_wares_per_minute);
+        // with `solve_cycles` the cycle is passed again until no modules are
+        // added, i.e. the spare output of the last pass covers its consumption
+        let passes = self.path.iter().filter(|id| *id == ware_id).count();
+        let diverges
+            = self.acc.iter().any(
+                  |(id, _, _, productions)| id == ware_id && productions.iter().any(|(count, _)| Self::MAX_CYCLE_MODULES <= *count)
+                  );
+        if 0 != passes && (!self.settings.solve_cycles || Self::MAX_CYCLE_PASSES <= passes || diverges) {
+            let start = self.path.iter().rposition(|id| id == ware_id).unwrap();
+            let mut cycle = self.path[start..].to_vec();
+            cycle.push(ware_id.to_string());
+            return Err(DataError::RecipeCycle(cycle));
+        }
+        let settings = self.settings;
+        // external supplies cover the demand first
+        let supply = settings.supply(ware_id);
+        let ware = self.wares.get(ware_id)?;
+        let items = self.pick_methods(ware, prioritylist, blacklist)?;
+        if self.find(ware_id).is_none() {
+            self.acc.push((ware_id.to_string(), 0f64, &ware.info, Vec::new()));
+        }
+        let (_, acc_wares_per_minute, _, productions) = self.find(ware_id).unwrap();
+        *acc_wares_per_minute += wares_per_minute;
+        let counts = split_module_counts(&items, (*acc_wares_per_minute - supply).max(0.), settings);
+        let counts_added
+            = counts.iter()
+              .map(|(count, ware_production)| {
+                  let prev_value = productions.iter().find(|(_, other)| other.method == ware_production.method).map_or(0, |(count, _)| *count);
+                  (count.saturating_sub(prev_value), *ware_production)
+              })
+              .filter(|(count_added, _)| 0 != *count_added)
+              .collect::<Vec<_>>();
+        *productions = counts;
 
-                      let to_add = *acc_count - prev_value;
-                      if 0 == to_add {
-                          None
-                      }
-                      else {
-                        Some((*acc_count - prev_value, *ware_production))
-                      }
-                  },
-                  Some((_, acc_wares_per_minute, _, None)) => {
-                      *acc_wares_per_minute += wares_per_minute;
-                      None
-                  },
-                  None => {
-                      let ware = self.wares.get(ware_id)?;
-                      let to_produce_opt
-                          = if !ware.production_methods.is_empty() {
-                                let ware_production = ware.find_desired_method(prioritylist, blacklist)?;
-                                let count = ware_production.fabrics_count_from_desired_wares_per_minute(wares_per_minute);
-                                Some((count, ware_production))
-                            }
-                            else {
-                                None
-                            };
-                      self.acc.push((ware_id.to_string(), wares_per_minute, &ware.info, to_produce_opt));
-                      to_produce_opt
-                  },
-              };
-        if let Some((count_added, ware_production)) = count_added_opt {
-            for (dependency_name, wares_per_minute) in ware_production.dependencies_per_minute() {
-                self.add_wares_rec(dependency_name, wares_per_minute*count_added as f64, prioritylist, blacklist)?;
+        if !counts_added.is_empty() {
+            self.path.push(ware_id.to_string());
+            for (count_added, ware_production) in counts_added {
+                for (dependency_name, wares_per_minute) in ware_production.dependencies_per_minute() {
+                    self.add_wares_rec(dependency_name, wares_per_minute*count_added as f64, prioritylist, blacklist)?;
+                }
             }
+            self.path.pop();
         }
         Ok(())
     }
     fn add_wares (&mut self, ware_id: &str, wares_per_minute: f64, prioritylist: &[String], blacklist: &[String]) -> Result<(), DataError> {
         self.add_wares_rec(ware_id, wares_per_minute, prioritylist, blacklist)
     }
-    fn add_fabrics (&mut self, ware_id: &str, fabrics_count: usize, prioritylist: &[String], blacklist: &[String]) -> Result<(), DataError> {
-        let wares_per_minute = self.wares.get(ware_id)?.find_desired_method(prioritylist, blacklist)?.wares_per_minute() * fabrics_count as f64;
-        self.add_wares(ware_id, wares_per_minute, prioritylist, blacklist)
+    // Workers of all the modules eat, new modules and the habitats of the
+    // workers are built of wares. The modules added for that have workers and
+    // are built too, so their needs are added until no modules are added.
+    fn add_station_needs (&mut self, prioritylist: &[String], blacklist: &[String]) -> Result<(), DataError> {
+        let worker_consumption = worker_consumption(self.wares, self.settings, prioritylist, blacklist)?;
+        let mut workers_fed = 0f64;
+        let mut habitats_built = 0;
+        // (ware_id, method, new modules) already built
+        let mut modules_built: Vec<(String, String, usize)> = Vec::new();
+        for _ in 0..Self::MAX_CYCLE_PASSES {
+            let mut needs: Vec<(&'a str, f64)> = Vec::new();
+            let workers = self.workers_count();
+            needs.extend(worker_consumption.iter().map(|(dependency, wares_per_minute)| (*dependency, wares_per_minute * (workers - workers_fed))));
+            workers_fed = workers;
+            if 0. < workers {
+                if let Some((habitat, consumption)) = habitat_build_consumption(self.wares, self.settings)? {
+                    let habitats = (workers / habitat.capacity as f64).ceil() as usize;
+                    needs.extend(consumption.into_iter().map(|(dependency, wares_per_minute)| (dependency, wares_per_minute * (habitats - habitats_built) as f64)));
+                    habitats_built = habitats;
+                }
+            }
+            for (ware_id, method, new) in self.new_modules() {
+                let built
+                    = match modules_built.iter_mut().find(|(other_id, other_method, _)| other_id == &ware_id && other_method == &method) {
+                          Some((_, _, built)) => built,
+                          None => {
+                              modules_built.push((ware_id.clone(), method.clone(), 0));
+                              &mut modules_built.last_mut().unwrap().2
+                          },
+                      };
+                if *built < new {
+                    needs.extend(module_build_consumption(self.wares, &ware_id, &method, self.settings)?.into_iter().map(|(dependency, wares_per_minute)| (dependency, wares_per_minute * (new - *built) as f64)));
+                    *built = new;
+                }
+            }
+            needs.retain(|(_, wares_per_minute)| 0. < *wares_per_minute);
+            if needs.is_empty() {
+                return Ok(());
+            }
+            for (dependency, wares_per_minute) in needs {
+                self.add_wares(dependency, wares_per_minute, prioritylist, blacklist)?;
+            }
+        }
+        Err(DataError::StationNeedsDiverge)
+    }
+    // (ware_id, method, modules above the existing ones)
+    fn new_modules (&self) -> Vec<(String, String, usize)> {
+        self.acc.iter()
+            .flat_map(|(ware_id, _, _, productions)| productions.iter().map(move |(count, ware_production)| (ware_id, count, ware_production)))
+            .map(|(ware_id, count, ware_production)| {
+                let existing = self.settings.existing(ware_id).filter(|existing| existing.method == ware_production.method).map_or(0, |existing| existing.count);
+                (ware_id.clone(), ware_production.method.clone(), count.saturating_sub(existing))
+            })
+            .collect()
+    }
+    // existing modules are staffed even if the plan leaves them idle
+    fn workers_count (&self) -> f64 {
+        self.acc.iter()
+            .flat_map(|(ware_id, _, _, productions)| productions.iter().map(move |(count, ware_production)| (ware_id, count, ware_production)))
+            .map(|(ware_id, count, ware_production)| {
+                let existing = self.settings.existing(ware_id).filter(|existing| existing.method == ware_production.method).map_or(0, |existing| existing.count);
+                *count.max(&existing) as f64 * self.wares.module_workers(ware_id, &ware_production.method, self.settings)
+            })
+            .sum()
+    }
+    // output of requested modules
+    fn fabrics_wares_per_minute (&self, ware_id: &str, fabrics_count: usize, prioritylist: &[String], blacklist: &[String]) -> Result<f64, DataError> {
+        let ware = self.wares.get(ware_id)?;
+        let ware_production
+            = match self.pick_method(ware, prioritylist, blacklist)? {
+                  Some(ware_production) => ware_production,
+                  None => ware.find_desired_method(prioritylist, blacklist, self.settings.ware_methods(ware_id))?,
+              };
+        Ok(ware_production.wares_per_minute_with_effects(self.settings) * fabrics_count as f64)
     }
 }
 
-#[derive(Debug, Clone)]
+// (ware, desired wares per minute, (split item, module count, new module count)
+// or none if imported)
+type SolverWare<'a> = (&'a SingleWare, f64, Option<Vec<(SplitItem<'a>, microlp::Variable, microlp::Variable)>>);
+
+// Plans the whole recipe graph at once as an integer program: a module count
+// per allowed (ware, method), production of every ware minus its consumption
+// by all the modules must cover the request. Unlike `Fabrics` it may pick
+// another method or more modules of a ware to save modules elsewhere.
+struct OptimalFabrics<'a> {
+    wares:       &'a Wares,
+    settings:    &'a PlanSettings,
+    unbuildable: &'a [String],
+}
+impl<'a> OptimalFabrics<'a> {
+    // a module count can't be unbounded for the branch and bound
+    const MAX_MODULES: i32 = 1_000_000;
+
+    fn new (wares: &'a Wares, settings: &'a PlanSettings, unbuildable: &'a [String]) -> Self {
+        Self {wares, settings, unbuildable}
+    }
+    // none if imported. Methods with a ratio make their share of the ware,
+    // fixed counts are exact next to them and the least count otherwise.
+    fn allowed_methods (&self, ware: &'a SingleWare, prioritylist: &[String], blacklist: &[String]) -> Result<Option<Vec<SplitItem<'a>>>, DataError> {
+        if self.settings.is_imported(&ware.info.ware_id) {
+            return Ok(None);
+        }
+        let mut result = ware.split_items(self.settings.ware_methods(&ware.info.ware_id))?;
+        if result.iter().any(|(_, ratio, _)| 0. < *ratio) {
+            return Ok(Some(result));
+        }
+        match self.usual_methods(ware, prioritylist, blacklist)? {
+            None if result.is_empty() => Ok(None),
+            methods_opt => {
+                for method in methods_opt.into_iter().flatten() {
+                    if !result.iter().any(|(other, ..)| other.method == method.method) {
+                        result.push((method, 0f64, 0));
+                    }
+                }
+                Ok(Some(result))
+            },
+        }
+    }
+    fn usual_methods (&self, ware: &'a SingleWare, prioritylist: &[String], blacklist: &[String]) -> Result<Option<Vec<&'a SingleWareProduction>>, DataError> {
+        if let Some(existing) = self.settings.existing(&ware.info.ware_id) {
+            return ware.production_methods.iter().find(|(name, _)| name == &existing.method).map(|(_, method)| Some(vec![method]))
+                       .ok_or(DataError::UnknownProductionMethod(existing.method.clone()));
+        }
+        let ware_methods_opt = self.settings.ware_methods(&ware.info.ware_id);
+        if let Some(WareMethods{method: Some(_), ..}) = ware_methods_opt {
+            return Ok(Some(vec![ware.find_desired_method(prioritylist, blacklist, ware_methods_opt)?]));
+        }
+        if !ware.is_buildable(self.unbuildable) {
+            return Ok(None);
+        }
+        let ware_blacklist = ware_methods_opt.map_or(&[][..], |ware_methods| &ware_methods.blacklist);
+        if let Some(method) = prioritylist.iter().filter(|p| !ware_blacklist.contains(p)).find_map(|p| ware.production_methods.iter().find(|(key, _)| key == p)) {
+            return Ok(Some(vec![&method.1]));
+        }
+        let methods = ware.production_methods.iter().filter(|(name, _)| !blacklist.contains(name) && !ware_blacklist.contains(name)).map(|(_, method)| method).collect::<Vec<_>>();
+        if methods.is_empty() {
+            return Err(DataError::AllMethodsBlacklisted(ware.info.ware_id.clone()));
+        }
+        Ok(Some(methods))
+    }
+    fn module_weight (&self, ware_id: &str, method: &SingleWareProduction, objective: SolverObjective) -> Result<f64, DataError> {
+        match objective {
+            SolverObjective::ModuleCount => Ok(1f64),
+            SolverObjective::BuildCost => self.wares.find_production_module(ware_id, &method.method)
+                                              .map(|module| module.info.price_average as f64)
+                                              .ok_or(DataError::ModuleNotFound(ware_id.to_string(), method.method.clone())),
+        }
+    }
+    fn solve (&self, desired_outputs: &[(String, f64)], objective: SolverObjective, prioritylist: &[String], blacklist: &[String]) -> Result<Vec<FabricsAccItem<'a>>, DataError> {
+        use microlp::{ComparisonOp, OptimizationDirection, Problem, Variable};
+
+        let worker_consumption = worker_consumption(self.wares, self.settings, prioritylist, blacklist)?;
+        let mut problem = Problem::new(OptimizationDirection::Minimize);
+        // found with the first module having workers
+        let mut habitat_opt: Option<HabitatBuild> = None;
+        // in the order of discovery from the request
+        let mut wares: Vec<SolverWare<'a>> = Vec::new();
+        let mut queue = desired_outputs.iter().map(|(ware_id, wares_per_minute)| (ware_id.as_str(), *wares_per_minute)).collect::<std::collections::VecDeque<_>>();
+        while let Some((ware_id, wares_per_minute)) = queue.pop_front() {
+            if let Some((_, desired, _)) = wares.iter_mut().find(|(ware, ..)| ware.info.ware_id == ware_id) {
+                *desired += wares_per_minute;
+                continue;
+            }
+            let ware = self.wares.get(ware_id)?;
+            let methods_opt
+                = match self.allowed_methods(ware, prioritylist, blacklist)? {
+                      Some(items) => {
+                          let has_ratios = items.iter().any(|(_, ratio, _)| 0. < *ratio);
+                          let mut result = Vec::new();
+                          for item in items {
+                              let (method, ratio, fixed) = item;
+                              let weight = self.module_weight(ware_id, method, objective)?;
+                              let max = if has_ratios && 0. == ratio { fixed as i32 } else { Self::MAX_MODULES };
+                              let (variable, new)
+                                  = match self.settings.existing(ware_id) {
+                                        // only the modules above the existing ones cost, the
+                                        // small weight keeps the spare ones idle
+                                        Some(existing) => {
+                                            let total = problem.add_integer_var(weight / Self::MAX_MODULES as f64, (0, Self::MAX_MODULES));
+                                            let new = problem.add_integer_var(weight, (0, Self::MAX_MODULES));
+                                            problem.add_constraint([(new, 1f64), (total, -1f64)], ComparisonOp::Ge, -(existing.count as f64));
+                                            (total, new)
+                                        },
+                                        None => {
+                                            let total = problem.add_integer_var(weight, (fixed as i32, max));
+                                            (total, total)
+                                        },
+                                    };
+                              result.push((item, variable, new));
+                              queue.extend(method.wares_dependencies.iter().map(|(dependency, _)| (dependency.as_str(), 0f64)));
+                              if 0. < self.wares.module_workers(ware_id, &method.method, self.settings) {
+                                  queue.extend(worker_consumption.iter().map(|(dependency, _)| (*dependency, 0f64)));
+                                  if habitat_opt.is_none() {
+                                      habitat_opt = habitat_build_consumption(self.wares, self.settings)?;
+                                      queue.extend(habitat_opt.iter().flat_map(|(_, consumption)| consumption.iter().map(|(dependency, _)| (*dependency, 0f64))));
+                                  }
+                              }
+                              queue.extend(module_build_consumption(self.wares, ware_id, &method.method, self.settings)?.into_iter().map(|(dependency, _)| (dependency, 0f64)));
+                          }
+                          Some(result)
+                      },
+                      None => None,
+                  };
+            wares.push((ware, wares_per_minute, methods_opt));
+        }
+
+        // habitats house the workers of all the modules and are all built
+        let habitats_opt
+            = match habitat_opt.as_ref() {
+                  Some((habitat, consumption)) => {
+                      let weight
+                          = match objective {
+                                SolverObjective::ModuleCount => 1f64,
+                                SolverObjective::BuildCost => self.wares.get(&habitat.module_ware)?.info.price_average as f64,
+                            };
+                      Some((problem.add_integer_var(weight, (0, Self::MAX_MODULES)), habitat.capacity, consumption))
+                  },
+                  None => None,
+              };
+        let mut jobs = habitats_opt.iter().map(|(habitats, capacity, _)| (*habitats, *capacity as f64)).collect::<Vec<_>>();
+
+        // ware_id -> module count -> wares per minute, positive if produced
+        let mut balances: HashMap<&str, HashMap<Variable, f64>> = HashMap::new();
+        let mut consumers: HashMap<&str, HashMap<Variable, f64>> = HashMap::new();
+        for (ware, _, methods_opt) in wares.iter() {
+            for ((method, ..), variable, new) in methods_opt.iter().flatten() {
+                *balances.entry(ware.info.ware_id.as_str()).or_default().entry(*variable).or_default() += method.wares_per_minute_with_effects(self.settings);
+                jobs.push((*variable, -self.wares.module_workers(&ware.info.ware_id, &method.method, self.settings)));
+                // the workers of a module eat as if it were one more input,
+                // construction is an input of the new modules
+                let needs
+                    = method.dependencies_per_minute().chain(module_workers_consumption(self.wares, &worker_consumption, &ware.info.ware_id, &method.method, self.settings)).map(|need| (*variable, need))
+                      .chain(module_build_consumption(self.wares, &ware.info.ware_id, &method.method, self.settings)?.into_iter().map(|need| (*new, need)));
+                for (modules, (dependency, wares_per_minute)) in needs {
+                    *balances.entry(dependency).or_default().entry(modules).or_default() -= wares_per_minute;
+                    *consumers.entry(dependency).or_default().entry(modules).or_default() += wares_per_minute;
+                }
+            }
+        }
+        if let Some((habitats, _, consumption)) = habitats_opt {
+            problem.add_constraint(jobs, ComparisonOp::Ge, 0f64);
+            for (dependency, wares_per_minute) in consumption.iter() {
+                *balances.entry(dependency).or_default().entry(habitats).or_default() -= wares_per_minute;
+                *consumers.entry(dependency).or_default().entry(habitats).or_default() += wares_per_minute;
+            }
+        }
+        for (ware, desired, methods_opt) in wares.iter() {
+            if let Some(methods) = methods_opt {
+                let required = *desired - self.settings.supply(&ware.info.ware_id);
+                let balance = balances.remove(ware.info.ware_id.as_str()).unwrap_or_default();
+                problem.add_constraint(balance.into_iter().collect::<Vec<_>>(), ComparisonOp::Ge, required);
+
+                // a method with a ratio makes at least its share of what the
+                // fixed modules don't, as the greedy planner does
+                let ratios = methods.iter().map(|((_, ratio, _), ..)| ratio).sum::<f64>();
+                let fixed = methods.iter().filter(|((_, ratio, _), ..)| 0. == *ratio).map(|((method, _, count), ..)| method.wares_per_minute_with_effects(self.settings) * *count as f64).sum::<f64>();
+                for ((method, ratio, _), variable, _) in methods.iter().filter(|((_, ratio, _), ..)| 0. < *ratio) {
+                    let share = ratio / ratios;
+                    let mut expression = HashMap::from([(*variable, method.wares_per_minute_with_effects(self.settings))]);
+                    for (consumer, wares_per_minute) in consumers.get(ware.info.ware_id.as_str()).into_iter().flatten() {
+                        *expression.entry(*consumer).or_default() -= share * wares_per_minute;
+                    }
+                    problem.add_constraint(expression.into_iter().collect::<Vec<_>>(), ComparisonOp::Ge, share * (required - fixed));
+                }
+            }
+        }
+        let solution = problem.solve().map_err(|e| DataError::SolverFailed(format!("{:?}", e)))?;
+        let count = |variable: Variable| solution.var_value_rounded(variable).max(0f64) as usize;
+
+        // consumption follows from the module counts chosen
+        let mut consumed: HashMap<&str, f64> = HashMap::new();
+        for (ware, _, methods_opt) in wares.iter() {
+            for ((method, ..), variable, new) in methods_opt.iter().flatten() {
+                for (dependency, wares_per_minute) in method.dependencies_per_minute().chain(module_workers_consumption(self.wares, &worker_consumption, &ware.info.ware_id, &method.method, self.settings)) {
+                    *consumed.entry(dependency).or_default() += wares_per_minute * count(*variable) as f64;
+                }
+                for (dependency, wares_per_minute) in module_build_consumption(self.wares, &ware.info.ware_id, &method.method, self.settings)? {
+                    *consumed.entry(dependency).or_default() += wares_per_minute * count(*new) as f64;
+                }
+            }
+        }
+        if let Some((habitats, _, consumption)) = habitats_opt {
+            for (dependency, wares_per_minute) in consumption.iter() {
+                *consumed.entry(dependency).or_default() += wares_per_minute * count(habitats) as f64;
+            }
+        }
+        let mut result = Vec::new();
+        for (ware, desired, methods_opt) in wares {
+            let wares_per_minute = desired + consumed.get(ware.info.ware_id.as_str()).copied().unwrap_or_default();
+            if 0. == wares_per_minute {
+                // reachable only through methods that were not chosen
+                continue;
+            }
+            let productions = methods_opt.into_iter().flatten().map(|((method, ..), variable, _)| (count(variable), method)).collect();
+            result.push((ware.info.ware_id.clone(), wares_per_minute, &ware.info, productions));
+        }
+        Ok(result)
+    }
+}
+
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
 struct Translations {
     // 'item' is ware or production method
     desired_unicode_id: String,
     unicode_id_to_item_id_to_translation: HashMap<String, HashMap<String, String>>,
+    // "description" or "factoryname" -> ware_id -> text, kept apart from the
+    // names so that user input is never reverse-translated into a description
+    unicode_id_to_ware_text_to_translation: HashMap<String, HashMap<String, HashMap<String, String>>>,
 }
 impl Translations {
     fn set_desired_unicode_id (&mut self, desired_unicode_id: String) {
@@ -648,7 +1638,17 @@ impl Translations {
         }
         None
     }
-    fn read_translation_value<'a> (xml: roxmltree::Node<'a, 'static>, node: roxmltree::Node<'a, 'static>) -> Option<String> {
+    fn get_ware_text (&self, ware_id: &str, atr: &'static str) -> Option<String> {
+        [self.desired_unicode_id.as_str(), "en"].iter()
+            .find_map(|unicode_id| self.unicode_id_to_ware_text_to_translation.get(*unicode_id)?.get(atr)?.get(ware_id).cloned())
+    }
+    fn find_text<'a> (langs: &[roxmltree::Node<'a, 'static>], page_id: &str, item_id: &str) -> Option<roxmltree::Node<'a, 'static>> {
+        // the same page may be continued by extensions, later ones override
+        langs.iter().rev()
+            .flat_map(|lang| lang.children().filter(|node| node.has_tag_name("page") && node.attribute("id").filter(|&id| id == page_id).is_some()))
+            .find_map(|page| page.children().find(|node| node.has_tag_name("t") && node.attribute("id").filter(|&id| id == item_id).is_some()))
+    }
+    fn read_translation_value<'a> (langs: &[roxmltree::Node<'a, 'static>], node: roxmltree::Node<'a, 'static>) -> Option<String> {
         //use regex::Replacer;
         //if node.text().unwrap().starts_with("(ARG S All-round Engine Mk1") {
         //    let a = 3;
@@ -659,7 +1659,7 @@ impl Translations {
             static ref RE_REF: regex::Regex = regex::Regex::new(r"\{(?P<page>[[:digit:]]+),[[:space:]]*(?P<itemid>[[:digit:]]+)\}").unwrap();
         }
         let mut content = node.text()?.trim().to_string();
-        if let Some(_) = RE_COMMENT.captures(&content) {
+        if RE_COMMENT.captures(&content).is_some() {
             content = RE_COMMENT.replace(&content, "").into_owned();
         }
         let mut is_bad = false;
@@ -670,11 +1670,9 @@ impl Translations {
                 let page_id = cap.name("page").unwrap().as_str();
                 let item_id = cap.name("itemid").unwrap().as_str();
 
-                if let Some(page) = xml.children().find(|node| node.has_tag_name("page") && node.attribute("id").filter(|&atr| atr == page_id).is_some()) {
-                    if let Some(node) = page.children().find(|node| node.has_tag_name("t") && node.attribute("id").filter(|&atr| atr == item_id).is_some()) {
-                        if let Some(result) = Self::read_translation_value(xml, node) {
-                            return result;
-                        }
+                if let Some(node) = Self::find_text(langs, page_id, item_id) {
+                    if let Some(result) = Self::read_translation_value(langs, node) {
+                        return result;
                     }
                 }
                 is_bad = true;
@@ -687,7 +1685,8 @@ impl Translations {
             None
         }
         else {
-            Some(content)
+            // descriptions escape brackets to not be taken as comments
+            Some(content.replace("\\(", "(").replace("\\)", ")"))
         }
 
         //if let Some(cap) = RE.captures(content) {
@@ -703,41 +1702,50 @@ impl Translations {
         //    Some(content.to_string())
         //}
     }
-    fn load_from_xml_all (all_xml: &[roxmltree::Document<'static>], wares: &Wares, lang_ids: Vec<(String, String)>) -> Self {
+    fn load_from_xml_all (all_xml: &[&roxmltree::Document<'static>], wares: &Wares, lang_ids: Vec<(String, String)>) -> Self {
         let mut unicode_id_to_item_id_to_translation = HashMap::new();
+        let mut unicode_id_to_ware_text_to_translation = HashMap::new();
         for (unicode_lang_id, x4_lang_id) in lang_ids.iter() {
             let mut item_to_translation = HashMap::new();
-            if let Some(lang) = all_xml.iter().find_map(
-                                    |doc| {
-                                        let root = doc.root_element();
-                                        if root.has_tag_name("language") && root.attribute("id").filter(|&lang_id| lang_id == x4_lang_id).is_some() {
-                                            Some(root)
-                                        }
-                                        else {
-                                            None
-                                        }
-                                    })
-            {
-                for (item_id, pageno) in wares.id_to_dsc.iter().map(|(_, ware)| ware.production_methods.iter().map(|(_, method)| (&method.method, &method.translation)).chain(std::iter::once((&ware.info.ware_id, &ware.translation)).filter_map(|(id, pageno_opt)| pageno_opt.as_ref().map(|v| (id, v))))).flatten() {
+            let mut ware_text_to_translation = HashMap::new();
+            let langs = all_xml.iter().filter_map(
+                            |doc| {
+                                let root = doc.root_element();
+                                if root.has_tag_name("language") && root.attribute("id").filter(|&lang_id| lang_id == x4_lang_id).is_some() {
+                                    Some(root)
+                                }
+                                else {
+                                    None
+                                }
+                            }).collect::<Vec<_>>();
+            if !langs.is_empty() {
+                for (item_id, pageno) in wares.methods.iter().filter_map(|method| method.translation.as_ref().map(|v| (&method.id, v))).chain(wares.id_to_dsc.iter().flat_map(|(_, ware)| ware.production_methods.iter().map(|(_, method)| (&method.method, &method.translation)).chain(std::iter::once((&ware.info.ware_id, &ware.translation)).filter_map(|(id, pageno_opt)| pageno_opt.as_ref().map(|v| (id, v)))))) {
                     if item_to_translation.contains_key(item_id) {
                         continue;
                     }
-                    if let Some(page) = lang.children().find(|node| node.has_tag_name("page") && node.attribute("id").filter(|&id| id == pageno.page.as_str()).is_some()) {
-                        if let Some(translation) = page.children().find(|node| node.has_tag_name("t") && node.attribute("id").filter(|&id| id == pageno.id.as_str()).is_some()) {
-                            if let Some(new_string) = Self::read_translation_value(lang, translation) {
-                                item_to_translation.insert(item_id.clone(), new_string);
-                            }
+                    if let Some(translation) = Self::find_text(&langs, &pageno.page, &pageno.id) {
+                        if let Some(new_string) = Self::read_translation_value(&langs, translation) {
+                            item_to_translation.insert(item_id.clone(), new_string);
                         }
                     }
                 }
+                for (ware_id, atr, pageno) in wares.id_to_dsc.iter().flat_map(|(_, ware)| [("description", &ware.description), ("factoryname", &ware.factoryname)].into_iter().filter_map(|(atr, pageno_opt)| pageno_opt.as_ref().map(|v| (&ware.info.ware_id, atr, v)))) {
+                    if let Some(new_string) = Self::find_text(&langs, &pageno.page, &pageno.id).and_then(|translation| Self::read_translation_value(&langs, translation)) {
+                        ware_text_to_translation.entry(atr.to_string()).or_insert_with(HashMap::new).insert(ware_id.clone(), new_string);
+                    }
+                }
             }
             if !item_to_translation.is_empty() {
                 unicode_id_to_item_id_to_translation.insert(unicode_lang_id.clone(), item_to_translation);
             }
+            if !ware_text_to_translation.is_empty() {
+                unicode_id_to_ware_text_to_translation.insert(unicode_lang_id.clone(), ware_text_to_translation);
+            }
         }
 
         Self {
             unicode_id_to_item_id_to_translation,
+            unicode_id_to_ware_text_to_translation,
             desired_unicode_id: "en".to_string(),
         }
     }
@@ -748,12 +1756,24 @@ impl Translations {
         //let (string, doc) = origin.get_mut();
         let (_, doc) = origin.get_mut();
         //let translations_ids = find_langs(string.as_str(), &dbg_path)?;
-        let me = Self::load_from_xml_all(&doc, wares, lang_ids);
+        let me = Self::load_from_xml_all(&doc.iter().collect::<Vec<_>>(), wares, lang_ids);
         Ok(me)
     }
-    fn load (gamedir: &std::path::Path, wares: &Wares, lang_ids: Vec<(String, String)>) -> Result<Self, DataError> {
-        let (content, dbg_path) = load_string_file(gamedir, TRANSLATIONS_FILE)?;
-        Self::load_from_string(content, wares, lang_ids, dbg_path)
+    fn load (files: &GameFiles, extensions: &[(String, GameFiles)], wares: &Wares, lang_ids: Vec<(String, String)>) -> Result<Self, DataError> {
+        let mut origins = Vec::new();
+        for files in std::iter::once(files).chain(extensions.iter().map(|(_, files)| files)) {
+            for (_, x4_lang_id) in lang_ids.iter() {
+                let path = translation_path(x4_lang_id);
+                if !files.contains(&path) {
+                    // not every language listed is shipped
+                    continue;
+                }
+                let (content, dbg_path) = load_game_string_file(files, &path)?;
+                origins.push(read_xml(content, find_whole_xml_slice, "translations", &dbg_path)?);
+            }
+        }
+        let docs = origins.iter_mut().flat_map(|origin| origin.get_mut().1.iter()).collect::<Vec<_>>();
+        Ok(Self::load_from_xml_all(&docs, wares, lang_ids))
     }
 }
 
@@ -826,7 +1846,7 @@ impl serde::Serialize for CountsOutput {
             S: serde::Serializer {
         let string = match self {
                          Self::Produce(s, v) => format!("Produce({}, {})", s, v),
-                         Self::Import => format!("Import"),
+                         Self::Import => "Import".to_string(),
                      };
         serializer.serialize_str(&string)
     }
@@ -870,10 +1890,520 @@ impl<'de> serde::Deserialize<'de> for CountsOutput {
     }
 }
 
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub struct ExistingModules {
+    pub ware:   String,
+    pub method: String,
+    pub count:  usize,
+}
+#[derive(Debug, Clone, Default, serde::Serialize, serde::Deserialize)]
+pub struct WareMethods {
+    pub ware:      String,
+    // the only method to use, even if blacklisted or "noplayerbuild"
+    #[serde(default)]
+    pub method:    Option<String>,
+    #[serde(default)]
+    pub blacklist: Vec<String>,
+    // several methods at once, without ratios the rest goes to the usual method
+    #[serde(default)]
+    pub split:     Vec<MethodSplit>,
+}
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub struct MethodSplit {
+    pub method: String,
+    pub share:  MethodShare,
+}
+// Ratios are relative to the other ratios of the ware, they split what the
+// fixed module counts don't make
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum MethodShare {
+    Ratio(f64),
+    Fabrics(usize),
+}
+impl std::fmt::Display for MethodShare {
+    fn fmt (&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Self::Ratio(v) => write!(f, "Ratio({})", v),
+            Self::Fabrics(v) => write!(f, "Fabrics({})", v),
+        }
+    }
+}
+impl std::str::FromStr for MethodShare {
+    type Err = String;
+    fn from_str (s: &str) -> Result<Self, Self::Err> {
+        use nom::combinator::{all_consuming, map};
+        use nom::branch::alt;
+        use nom::bytes::complete::tag;
+        use nom::sequence::delimited;
+
+        let (_, ok) = all_consuming(alt((
+                          map(delimited(tag("Ratio("), nom::number::complete::double::<_,()>, tag(")")), Self::Ratio),
+                          map(delimited(tag("Fabrics("), nom::character::complete::u32, tag(")")), |u| Self::Fabrics(u as usize)),
+                          )))(s).map_err(|_| format!("Either Ratio(f64) either Fabrics(usize), got \"{}\"", s))?;
+        Ok(ok)
+    }
+}
+impl serde::Serialize for MethodShare {
+    fn serialize<S: serde::Serializer> (&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+impl<'de> serde::Deserialize<'de> for MethodShare {
+    fn deserialize<D: serde::Deserializer<'de>> (deserializer: D) -> Result<Self, D::Error> {
+        <String as serde::Deserialize>::deserialize(deserializer)?.parse().map_err(serde::de::Error::custom)
+    }
+}
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub struct ExternalSupply {
+    pub ware:             String,
+    pub wares_per_minute: f64,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SolverObjective {
+    // the least modules in total
+    ModuleCount,
+    // the cheapest modules to build, by the average price of module wares
+    BuildCost,
+}
+
+// Workers of the production modules, `workforce_percent` of the jobs of a
+// module macro are staffed, so it must be above 0. They live in habitats and consume food and
+// medical supplies, as the `workunit_busy` ware of their race says.
+#[derive(Debug, Clone, Default, serde::Serialize, serde::Deserialize)]
+#[serde(default)]
+pub struct Workforce {
+    // the method of `workunit_busy`, the usual method choice if none
+    pub method:  Option<String>,
+    // habitat module to build, e.g. "Argon M Habitat", the one housing the
+    // most workers if none
+    pub habitat: Option<String>,
+}
+
+// Station conditions the production rates are calculated for
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+#[serde(default)]
+pub struct PlanSettings {
+    // 0 - no workers, 100 - all modules fully staffed
+    pub workforce_percent: f64,
+    // sector sunlight, 100 is the base rate for solar power
+    pub sunlight_percent:  f64,
+    // pick "noplayerbuild" methods (xenon, research, recycling) without
+    // prioritizing them explicitly
+    pub allow_noplayerbuild: bool,
+    // none for the greedy planner
+    pub solver: Option<SolverObjective>,
+    // the greedy planner fails on a recipe cycle unless asked to repeat it
+    // until it converges, the solver always balances cycles
+    pub solve_cycles: bool,
+    // none for no economic report
+    pub price: Option<PricePoint>,
+    // none to leave the workers out, they eat what the plan makes
+    pub workforce: Option<Workforce>,
+    // none to buy the construction wares of new modules, otherwise they are
+    // made by the plan within that many hours
+    pub build_hours: Option<f64>,
+    // none for no storage plan, otherwise the storage holds that many hours
+    // of what the station buys, makes for itself and sells
+    pub storage_hours: Option<f64>,
+    // modules of the station already built, used before adding new ones
+    pub existing_modules:  Vec<ExistingModules>,
+    // wares brought from outside, e.g. by traders, cover the demand first
+    pub external_supplies: Vec<ExternalSupply>,
+    // per-ware method choice, also filled from the request rows
+    pub ware_methods:      Vec<WareMethods>,
+    // wares bought even if they could be produced, without their inputs
+    pub import:            Vec<String>,
+    // ships mining the imported minables, one per transport
+    pub miners:            Vec<MinerShip>,
+}
+impl Default for PlanSettings {
+    fn default () -> Self {
+        Self {
+            workforce_percent:   0f64,
+            sunlight_percent:    100f64,
+            allow_noplayerbuild: false,
+            solver:              None,
+            solve_cycles:        false,
+            price:               None,
+            workforce:           None,
+            build_hours:         None,
+            storage_hours:       None,
+            existing_modules:    Vec::new(),
+            external_supplies:   Vec::new(),
+            ware_methods:        Vec::new(),
+            import:              Vec::new(),
+            miners:              Vec::new(),
+        }
+    }
+}
+impl PlanSettings {
+    fn validate (&self) -> Result<(), DataError> {
+        if !(0f64..=100f64).contains(&self.workforce_percent) {
+            return Err(DataError::InconsistentRequest("workforce_percent must be in 0..=100"));
+        }
+        if self.workforce.is_some() && 0f64 == self.workforce_percent {
+            return Err(DataError::InconsistentRequest("workforce needs workforce_percent above 0"));
+        }
+        if !(0f64 < self.sunlight_percent && self.sunlight_percent.is_finite()) {
+            return Err(DataError::InconsistentRequest("sunlight_percent must be positive"));
+        }
+        if self.existing_modules.iter().enumerate().any(|(idx, existing)| self.existing_modules[..idx].iter().any(|other| other.ware == existing.ware)) {
+            return Err(DataError::InconsistentRequest("existing_modules must list a ware once"));
+        }
+        if self.external_supplies.iter().any(|supply| 0f64 > supply.wares_per_minute) {
+            return Err(DataError::InconsistentRequest("external_supplies must not be negative"));
+        }
+        if self.miners.iter().any(|miner| !(0f64 < miner.cargo_volume && 0f64 < miner.cycle_minutes)) {
+            return Err(DataError::InconsistentRequest("miners must have cargo_volume and cycle_minutes"));
+        }
+        if self.build_hours.is_some_and(|build_hours| !(0f64 < build_hours && build_hours.is_finite())) {
+            return Err(DataError::InconsistentRequest("build_hours must be positive"));
+        }
+        if self.storage_hours.is_some_and(|storage_hours| !(0f64 < storage_hours && storage_hours.is_finite())) {
+            return Err(DataError::InconsistentRequest("storage_hours must be positive"));
+        }
+        if let Some(PricePoint::Fraction(fraction)) = self.price {
+            if !(0f64..=1f64).contains(&fraction) {
+                return Err(DataError::InconsistentRequest("price fraction must be from 0 to 1"));
+            }
+        }
+        if self.existing_modules.iter().any(|existing| self.is_imported(&existing.ware)) {
+            return Err(DataError::InconsistentRequest("existing_modules of an imported ware"));
+        }
+        if self.ware_methods.iter().enumerate().any(|(idx, ware_methods)| self.ware_methods[..idx].iter().any(|other| other.ware == ware_methods.ware)) {
+            return Err(DataError::InconsistentRequest("ware_methods must list a ware once"));
+        }
+        for ware_methods in self.ware_methods.iter() {
+            if let Some(method) = ware_methods.method.iter().chain(ware_methods.split.iter().map(|split| &split.method)).find(|method| ware_methods.blacklist.contains(method)) {
+                return Err(DataError::PriorityBlackListsIntersection(method.clone()));
+            }
+            if self.is_imported(&ware_methods.ware) && (ware_methods.method.is_some() || !ware_methods.split.is_empty()) {
+                return Err(DataError::InconsistentRequest("an imported ware can't have its methods chosen"));
+            }
+            if ware_methods.split.is_empty() {
+                continue;
+            }
+            if ware_methods.method.is_some() {
+                return Err(DataError::InconsistentRequest("ware_methods can't both pin and split a ware"));
+            }
+            if self.existing(&ware_methods.ware).is_some() {
+                return Err(DataError::InconsistentRequest("existing_modules fix the method, the ware can't be split"));
+            }
+            if ware_methods.split.iter().any(|split| matches!(split.share, MethodShare::Ratio(ratio) if !(0f64 < ratio && ratio.is_finite()))) {
+                return Err(DataError::InconsistentRequest("split ratios must be positive"));
+            }
+        }
+        Ok(())
+    }
+    fn is_imported (&self, ware_id: &str) -> bool {
+        self.import.iter().any(|imported| imported == ware_id)
+    }
+    fn ware_methods (&self, ware_id: &str) -> Option<&WareMethods> {
+        self.ware_methods.iter().find(|ware_methods| ware_methods.ware == ware_id)
+    }
+    fn existing (&self, ware_id: &str) -> Option<&ExistingModules> {
+        self.existing_modules.iter().find(|existing| existing.ware == ware_id)
+    }
+    fn supply (&self, ware_id: &str) -> f64 {
+        self.external_supplies.iter().filter(|supply| supply.ware == ware_id).fold(0f64, |acc, supply| acc + supply.wares_per_minute)
+    }
+}
+
+// How much of a ware the planned modules make and the plan needs, per minute.
+// Module counts are rounded up, so there is some surplus: sellable wares and
+// partially idle modules. Imported wares produce nothing, their surplus is
+// the negative amount to import.
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct WareBalance {
+    pub produced_per_minute: f64,
+    // by other modules of the plan and the requested output
+    pub consumed_per_minute: f64,
+    // the requested part of the consumption
+    pub requested_per_minute: f64,
+    pub surplus_per_minute:  f64,
+    pub utilization_percent: f64,
+}
+impl WareBalance {
+    fn new (produced_per_minute: f64, consumed_per_minute: f64, requested_per_minute: f64) -> Self {
+        Self {
+            produced_per_minute,
+            consumed_per_minute,
+            requested_per_minute,
+            surplus_per_minute: produced_per_minute - consumed_per_minute,
+            utilization_percent: if 0. == produced_per_minute { 0. } else { 100. * consumed_per_minute / produced_per_minute },
+        }
+    }
+}
+
+// Modules of a produced ware, all zero if imported. `total` is the count of
+// `CountsOutput::Produce`, existing modules may exceed what the plan needs.
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct ModuleCounts {
+    pub existing: usize,
+    pub new:      usize,
+    pub total:    usize,
+}
+
+// Prices of the report, `Fraction` is a point from min (0) to max (1)
+#[derive(Debug, Clone, Copy, PartialEq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PricePoint {
+    Min,
+    Average,
+    Max,
+    Fraction(f64),
+}
+
+// Requested output and the surplus are sold, supplies and the deficit are
+// bought. `value_added` is what one module makes over its inputs.
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct WareEconomy {
+    pub price:                      f64,
+    pub revenue_per_hour:           f64,
+    pub cost_per_hour:              f64,
+    pub profit_per_hour:            f64,
+    pub profit_per_module_per_hour: f64,
+}
+impl WareEconomy {
+    fn new (info: &SingleWareInfo, price_point: PricePoint, balance: &WareBalance, requested_per_minute: f64, supply_per_minute: f64, value_added_per_minute: f64) -> Self {
+        let price = info.price(price_point);
+        let sold = requested_per_minute + balance.surplus_per_minute.max(0.);
+        let bought = supply_per_minute + (-balance.surplus_per_minute).max(0.);
+        Self {
+            price,
+            revenue_per_hour: 60. * sold * price,
+            cost_per_hour: 60. * bought * price,
+            profit_per_hour: 60. * (sold - bought) * price,
+            profit_per_module_per_hour: 60. * value_added_per_minute,
+        }
+    }
+}
+
+// A miner brings `cargo_volume` of its transport ("solid", "liquid") every
+// `cycle_minutes`, flight and unloading included
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub struct MinerShip {
+    pub transport:     String,
+    pub cargo_volume:  f64,
+    pub cycle_minutes: f64,
+}
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct MiningWare {
+    pub ware:            String,
+    pub transport:       String,
+    pub wares_per_hour:  f64,
+    pub volume_per_hour: f64,
+}
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct MiningTransport {
+    pub transport:       String,
+    pub volume_per_hour: f64,
+    // none without a miner of this transport
+    pub miners:          Option<usize>,
+}
+// Imported minable wares of a plan, from (ware, info, imported per minute)
+pub fn calc_mining<'a, It: Iterator<Item=(&'a str, &'a SingleWareInfo, f64)>> (imports: It, miners: &[MinerShip]) -> (Vec<MiningWare>, Vec<MiningTransport>) {
+    let mut wares: Vec<MiningWare> = Vec::new();
+    for (ware, info, wares_per_minute) in imports {
+        if !info.tags.iter().any(|tag| tag == "minable") || 0. >= wares_per_minute {
+            continue;
+        }
+        wares.push(MiningWare {
+            ware:            ware.to_string(),
+            transport:       info.transport.clone(),
+            wares_per_hour:  60. * wares_per_minute,
+            volume_per_hour: 60. * wares_per_minute * info.volume as f64,
+        });
+    }
+    let mut transports: Vec<MiningTransport> = Vec::new();
+    for ware in wares.iter() {
+        match transports.iter_mut().find(|transport| transport.transport == ware.transport) {
+            Some(transport) => transport.volume_per_hour += ware.volume_per_hour,
+            None => transports.push(MiningTransport{transport: ware.transport.clone(), volume_per_hour: ware.volume_per_hour, miners: None}),
+        }
+    }
+    for transport in transports.iter_mut() {
+        transport.miners
+            = miners.iter().find(|miner| miner.transport == transport.transport)
+              .map(|miner| (transport.volume_per_hour / (miner.cargo_volume * 60. / miner.cycle_minutes)).ceil() as usize);
+    }
+    (wares, transports)
+}
+
+// Workers of a plan and the habitat modules housing them
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct Habitats {
+    pub workers:  f64,
+    // module ware id and its translation
+    pub ware_id:  String,
+    pub habitat:  String,
+    pub capacity: u32,
+    pub habitats: usize,
+}
+
+// Construction wares of the modules to build, a module ware's own production
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct BuildWare {
+    pub ware:    String,
+    pub amount:  f64,
+    pub price:   f64,
+    pub credits: f64,
+}
+// One blueprint per module ware, at the average price as in `PlannedModule`
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct BuildTotal {
+    pub wares_credits:      f64,
+    pub blueprints_credits: f64,
+    pub credits:            f64,
+}
+
+// The module ware of a produced ware, what is bought and placed. The blueprint
+// costs the average price of the module ware.
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct PlannedModule {
+    // not translated, unlike `module`
+    pub ware_id:         String,
+    pub module:          String,
+    pub race:            Option<String>,
+    pub blueprint_price: u32,
+}
+
+// Cargo volume a ware takes for `PlanSettings::storage_hours`. Inputs are
+// bought as in `WareEconomy`, outputs are sold, intermediates are the rest of
+// the consumption, made and used by the station.
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct WareStorage {
+    pub input_volume:        f64,
+    pub intermediate_volume: f64,
+    pub output_volume:       f64,
+}
+impl WareStorage {
+    fn new (info: &SingleWareInfo, storage_hours: f64, balance: &WareBalance, requested_per_minute: f64, supply_per_minute: f64) -> Self {
+        let sold = requested_per_minute + balance.surplus_per_minute.max(0.);
+        let bought = supply_per_minute + (-balance.surplus_per_minute).max(0.);
+        let made_and_used = (balance.consumed_per_minute - requested_per_minute - bought).max(0.);
+        let volume_per_minute = 60. * storage_hours * info.volume as f64;
+        Self {
+            input_volume:        bought * volume_per_minute,
+            intermediate_volume: made_and_used * volume_per_minute,
+            output_volume:       sold * volume_per_minute,
+        }
+    }
+}
+// Storage of a plan per transport and the modules to hold it
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct TransportStorage {
+    pub transport:           String,
+    pub input_volume:        f64,
+    pub intermediate_volume: f64,
+    pub output_volume:       f64,
+    pub volume:              f64,
+    // the largest storage module of the transport, none if not found
+    pub module:              Option<String>,
+    pub modules:             Option<usize>,
+}
+
+// A row of a plan, one per (ware, method)
 #[derive(Debug, Clone)]
+pub struct PlannedWare {
+    pub ware:             String,
+    pub wares_per_minute: f64,
+    pub counts:           CountsOutput,
+    // production method id, kept untranslated, none if imported
+    pub method_id:        Option<String>,
+    pub single_ware:      SingleWare,
+    pub balance:          WareBalance,
+    pub modules:          ModuleCounts,
+    // none without a price point
+    pub economy:          Option<WareEconomy>,
+    // none if imported or not found
+    pub module:           Option<PlannedModule>,
+    // none without storage hours
+    pub storage:          Option<WareStorage>,
+}
+
+// A ware flowing to its consumer in the production tree of a requested output.
+// A ware shared by several consumers is split by their consumption:
+// `share_percent` of its consumption goes to this one, `modules` are those
+// running for it. Workers and construction are not in any tree.
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct ProductionNode {
+    pub ware:             String,
+    // none if imported
+    pub method:           Option<String>,
+    pub wares_per_minute: f64,
+    pub share_percent:    f64,
+    pub modules:          f64,
+    // empty for imports and a ware already up the tree, i.e. a recipe cycle
+    pub inputs:           Vec<ProductionNode>,
+}
+// The node of row `idx` for a consumer taking `wares_per_minute` of it, `path`
+// has the rows up the tree. The output of a row goes to its consumers and its
+// surplus by their part, so idle modules feed no one.
+fn production_node (rows: &[&PlannedWare], productions: &[Option<&SingleWareProduction>], idx: usize, wares_per_minute: f64, path: &mut Vec<usize>) -> ProductionNode {
+    let PlannedWare{ware, counts, modules, balance, ..} = rows[idx];
+    let method
+        = match counts {
+              CountsOutput::Produce(method, _) => Some(method.clone()),
+              CountsOutput::Import => None,
+          };
+    let share
+        = if 0. == balance.consumed_per_minute {
+              0.
+          }
+          else {
+              wares_per_minute / balance.consumed_per_minute
+          };
+    let output = balance.produced_per_minute.max(balance.consumed_per_minute);
+    let modules_count
+        = if 0. == output {
+              0.
+          }
+          else {
+              modules.total as f64 * wares_per_minute / output
+          };
+    let mut inputs = Vec::new();
+    if let Some(production) = productions[idx].filter(|_| !path.contains(&idx)) {
+        path.push(idx);
+        for (dependency, dependency_per_minute) in production.dependencies_per_minute() {
+            let flow = dependency_per_minute * modules_count;
+            // a ware made by several methods is taken from each by its part
+            let dependency_rows = rows.iter().enumerate().filter(|(_, row)| row.single_ware.info.ware_id == dependency).map(|(dependency_idx, _)| dependency_idx).collect::<Vec<_>>();
+            let consumed = dependency_rows.iter().map(|dependency_idx| rows[*dependency_idx].balance.consumed_per_minute).sum::<f64>();
+            for dependency_idx in dependency_rows.iter() {
+                let row_share
+                    = if 0. == consumed {
+                          1. / dependency_rows.len() as f64
+                      }
+                      else {
+                          rows[*dependency_idx].balance.consumed_per_minute / consumed
+                      };
+                inputs.push(production_node(rows, productions, *dependency_idx, flow * row_share, path));
+            }
+        }
+        path.pop();
+    }
+    ProductionNode {
+        ware: ware.clone(),
+        method,
+        wares_per_minute,
+        share_percent: 100. * share,
+        modules: modules_count,
+        inputs,
+    }
+}
+
+// (desired_outputs, prioritylist, blacklist) with item ids instead of translations
+type ValidatedRequest = (Vec<(String, CountsInput)>, Vec<String>, Vec<String>);
+
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
 pub struct Data {
     wares: Wares,
     translations: Translations,
+    // (virtual path, catalog it was read from), empty if loaded from strings
+    sources: Vec<(String, std::path::PathBuf)>,
 }
 
 impl Data {
@@ -886,22 +2416,101 @@ impl Data {
         Ok(Self {
             wares,
             translations,
+            sources: Vec::new(),
         })
     }
-    pub fn load_data (gamedir: &std::path::Path) -> Result<Self, DataError> {
-        let (wares, lang_ids_map) = Wares::load_wares_translationids_and_productionmethods(gamedir)?;
-        let translations = Translations::load(gamedir, &wares, lang_ids_map)?;
+    pub fn load_data (gamedir: &std::path::Path, extensions: &ExtensionsSelection) -> Result<Self, DataError> {
+        let (files, extensions) = Self::open_game_files(gamedir, extensions, false)?;
+        Self::load_data_files(files, extensions)
+    }
+    // Loads from the tree extracted by X Catalog Tool
+    pub fn load_data_unpacked (dir: &std::path::Path, extensions: &ExtensionsSelection) -> Result<Self, DataError> {
+        let (files, extensions) = Self::open_game_files(dir, extensions, true)?;
+        Self::load_data_files(files, extensions)
+    }
+    // Same as `load_data`/`load_data_unpacked`, but reuses data parsed by
+    // a previous run if none of the source files changed since
+    pub fn load_data_cached (gamedir: &std::path::Path, extensions: &ExtensionsSelection, is_unpacked: bool, cache_dir: &std::path::Path) -> Result<Self, DataError> {
+        let (files, extensions) = Self::open_game_files(gamedir, extensions, is_unpacked)?;
+        // translations are the only files not listed by path
+        let unpacked_paths = [WARES_PATH, LANGUAGES_PATH, "t", MACROS_INDEX_PATH, PRODUCTION_MACROS_DIR, STORAGE_MACROS_DIR, HABITAT_MACROS_DIR];
+        let mut fingerprints = files.fingerprint(&unpacked_paths)?;
+        for (_, extension_files) in extensions.iter() {
+            fingerprints.extend(extension_files.fingerprint(&unpacked_paths)?);
+        }
+        let key = CacheKey {
+                      version:    env!("CARGO_PKG_VERSION").to_string(),
+                      format:     cache::CACHE_FORMAT_VERSION,
+                      gamedir:    gamedir.to_path_buf(),
+                      unpacked:   is_unpacked,
+                      extensions: extensions.iter().map(|(id, _)| id.clone()).collect(),
+                      files:      fingerprints,
+                  };
+        let cache_path = cache::cache_file_path(cache_dir, &key);
+        if let Some(data) = cache::load(&cache_path, &key) {
+            return Ok(data);
+        }
+        let data = Self::load_data_files(files, extensions)?;
+        // the cache only saves time, failing to write it is not an error
+        let _ = cache::store(&cache_path, &key, &data);
+        Ok(data)
+    }
+    fn open_game_files (gamedir: &std::path::Path, extensions: &ExtensionsSelection, is_unpacked: bool) -> Result<(GameFiles, Vec<(String, GameFiles)>), DataError> {
+        let files
+            = if is_unpacked {
+                  GameFiles::Unpacked(gamedir.to_path_buf())
+              }
+              else {
+                  GameFiles::Catalogs(CatalogSet::load_numbered(gamedir, "")?)
+              };
+        let extensions = load_extensions_files(gamedir, extensions, is_unpacked)?;
+        Ok((files, extensions))
+    }
+    fn load_data_files (files: GameFiles, extensions: Vec<(String, GameFiles)>) -> Result<Self, DataError> {
+        let (mut wares, lang_ids_map) = Wares::load_wares_translationids_and_productionmethods(&files, &extensions)?;
+        wares.load_module_macros(&files, &extensions)?;
+        let sources
+            = std::iter::once(&files).chain(extensions.iter().map(|(_, files)| files))
+              .flat_map(|files|
+                  [WARES_PATH.to_string(), LANGUAGES_PATH.to_string(), MACROS_INDEX_PATH.to_string()].into_iter()
+                  .chain(lang_ids_map.iter().map(|(_, x4_lang_id)| translation_path(x4_lang_id)))
+                  .filter_map(|path| files.source(&path).map(|source| (path, source)))
+                  )
+              .collect();
+        let translations = Translations::load(&files, &extensions, &wares, lang_ids_map)?;
         Ok(Self {
             wares,
             translations,
+            sources,
         })
     }
+    pub fn gen_sources_list (&self) -> Vec<(String, std::path::PathBuf)> {
+        self.sources.clone()
+    }
     pub fn change_default_lang (&mut self, desired_unicode_id: String) {
         self.translations.desired_unicode_id = desired_unicode_id;
     }
     pub fn gen_production_methods_list (&self) -> Vec<String> {
         self.wares.gen_production_methods_list()
     }
+    pub fn get_production_methods (&self) -> &[ProductionMethod] {
+        &self.wares.methods
+    }
+    pub fn get_production_modules (&self) -> &[ProductionModule] {
+        &self.wares.modules
+    }
+    pub fn get_storage_modules (&self) -> &[StorageModule] {
+        &self.wares.storages
+    }
+    pub fn get_habitat_modules (&self) -> &[HabitatModule] {
+        &self.wares.habitats
+    }
+    pub fn get_ware_description (&self, ware_id: &str) -> Option<String> {
+        self.translations.get_ware_text(ware_id, "description")
+    }
+    pub fn get_ware_factoryname (&self, ware_id: &str) -> Option<String> {
+        self.translations.get_ware_text(ware_id, "factoryname")
+    }
     pub fn gen_lang_list (&self) -> Vec<String> {
         self.translations.unicode_id_to_item_id_to_translation.keys().cloned().collect()
     }
@@ -952,7 +2561,7 @@ impl Data {
         }
         Ok(())
     }
-    fn validate_and_untranslate (&self, desired_outputs: Vec<(String, CountsInput)>, prioritylist: Vec<String>, blacklist: Vec<String>) -> Result<(Vec<(String, CountsInput)>, Vec<String>, Vec<String>), DataError> {
+    fn validate_and_untranslate (&self, desired_outputs: Vec<(String, CountsInput)>, prioritylist: Vec<String>, blacklist: Vec<String>) -> Result<ValidatedRequest, DataError> {
         let usr_translation_to_item_name = self.make_translation_to_item_map(desired_outputs.iter().map(|(usr_name, _)| usr_name).cloned().chain(prioritylist.iter().cloned()).chain(blacklist.iter().cloned()))?;
 
         let items = desired_outputs.into_iter().map(|(usr_name, counts)| (usr_translation_to_item_name.get(&usr_name).unwrap().clone(), counts)).collect();
@@ -963,43 +2572,334 @@ impl Data {
 
         Ok((items, prioritylist, blacklist))
     }
-    pub fn calc_required_fabric_counts (&self, desired_outputs: Vec<(String, CountsInput)>, prioritylist: Vec<String>, blacklist: Vec<String>) -> Result<Vec<(String, f64, CountsOutput, SingleWare)>, DataError> {
-        let (desired_outputs, prioritylist, blacklist) = self.validate_and_untranslate(desired_outputs, prioritylist, blacklist)?;
+    // Existing modules and supplies name wares and methods the same way as the request
+    fn untranslate_settings (&self, settings: &PlanSettings) -> Result<PlanSettings, DataError> {
+        let usr_translation_to_item_name
+            = self.make_translation_to_item_map(
+                  settings.existing_modules.iter().flat_map(|existing| [existing.ware.clone(), existing.method.clone()])
+                  .chain(settings.external_supplies.iter().map(|supply| supply.ware.clone()))
+                  .chain(settings.import.iter().cloned())
+                  .chain(settings.ware_methods.iter().flat_map(|ware_methods| std::iter::once(ware_methods.ware.clone()).chain(ware_methods.method.clone()).chain(ware_methods.blacklist.iter().cloned()).chain(ware_methods.split.iter().map(|split| split.method.clone()))))
+                  .chain(settings.workforce.iter().flat_map(|workforce| workforce.method.iter().chain(workforce.habitat.iter()).cloned()))
+                  )?;
+        let untranslate = |usr: &String| usr_translation_to_item_name.get(usr).unwrap().clone();
+
+        let mut result = settings.clone();
+        for existing in result.existing_modules.iter_mut() {
+            existing.ware   = untranslate(&existing.ware);
+            existing.method = untranslate(&existing.method);
+        }
+        for supply in result.external_supplies.iter_mut() {
+            supply.ware = untranslate(&supply.ware);
+        }
+        result.import.iter_mut().for_each(|ware| *ware = untranslate(ware));
+        for ware_methods in result.ware_methods.iter_mut() {
+            ware_methods.ware = untranslate(&ware_methods.ware);
+            ware_methods.method = ware_methods.method.as_ref().map(untranslate);
+            ware_methods.blacklist.iter_mut().for_each(|method| *method = untranslate(method));
+            ware_methods.split.iter_mut().for_each(|split| split.method = untranslate(&split.method));
+        }
+        if let Some(workforce) = result.workforce.as_mut() {
+            workforce.method = workforce.method.as_ref().map(untranslate);
+            workforce.habitat = workforce.habitat.as_ref().map(untranslate);
+        }
+        Ok(result)
+    }
+    pub fn calc_required_fabric_counts (&self, desired_outputs: Vec<(String, CountsInput)>, prioritylist: Vec<String>, blacklist: Vec<String>, settings: &PlanSettings) -> Result<Vec<PlannedWare>, DataError> {
+        settings.validate()?;
+        let (desired_outputs, prioritylist, mut blacklist) = self.validate_and_untranslate(desired_outputs, prioritylist, blacklist)?;
+        let settings = &self.untranslate_settings(settings)?;
+        let unbuildable
+            = if settings.allow_noplayerbuild {
+                  Vec::new()
+              }
+              else {
+                  self.wares.gen_unbuildable_methods_list(&prioritylist)
+              };
+        blacklist.extend(unbuildable.iter().cloned());
+
+        // ware_id -> requested wares per minute, sold in the economic report
+        let mut requested: HashMap<String, f64> = HashMap::new();
+        let acc
+            = match settings.solver {
+                  None => {
+                      let mut fabrics = Fabrics::new(&self.wares, settings, &unbuildable);
+                      for (ware, desired_count) in desired_outputs {
+                          let wares_per_minute
+                              = match desired_count {
+                                    CountsInput::Fabrics(fabrics_count) => fabrics.fabrics_wares_per_minute(&ware, fabrics_count, &prioritylist, &blacklist)?,
+                                    CountsInput::WaresPerMinute(wares_per_minute) => wares_per_minute,
+                                };
+                          fabrics.add_wares(&ware, wares_per_minute, &prioritylist, &blacklist)?;
+                          *requested.entry(ware).or_default() += wares_per_minute;
+                      }
+                      fabrics.add_station_needs(&prioritylist, &blacklist)?;
+                      fabrics.into_acc()
+                  },
+                  Some(objective) => {
+                      // module counts are taken at the rate of the method the
+                      // greedy planner would use
+                      let desired_outputs
+                          = desired_outputs.into_iter()
+                            .map(|(ware, desired_count)| match desired_count {
+                                CountsInput::Fabrics(fabrics_count) => self.wares.get(&ware)?.find_desired_method(&prioritylist, &blacklist, settings.ware_methods(&ware))
+                                                                           .map(|method| (ware, method.wares_per_minute_with_effects(settings) * fabrics_count as f64)),
+                                CountsInput::WaresPerMinute(wares_per_minute) => Ok((ware, wares_per_minute)),
+                            })
+                            .collect::<Result<Vec<_>, DataError>>()?;
+                      for (ware, wares_per_minute) in desired_outputs.iter() {
+                          *requested.entry(ware.clone()).or_default() += wares_per_minute;
+                      }
+                      OptimalFabrics::new(&self.wares, settings, &unbuildable).solve(&desired_outputs, objective, &prioritylist, &blacklist)?
+                  },
+              };
+        let mut result = self.gen_planned_wares(acc, &requested, settings);
+        self.translate_planned_wares(&mut result);
+        Ok(result)
+    }
+    // Inverse of `calc_required_fabric_counts`: balance of a station built of
+    // these (ware, method, count) modules, a ware only consumed is imported
+    pub fn calc_station_balance (&self, modules: Vec<(String, String, usize)>, settings: &PlanSettings) -> Result<Vec<PlannedWare>, DataError> {
+        settings.validate()?;
+        let usr_translation_to_item_name = self.make_translation_to_item_map(modules.iter().flat_map(|(ware, method, _)| [ware.clone(), method.clone()]))?;
+        let settings = &self.untranslate_settings(settings)?;
+
+        let mut acc: Vec<FabricsAccItem> = Vec::new();
+        for (usr_ware, usr_method, count) in modules.iter() {
+            let ware = self.wares.get(usr_translation_to_item_name.get(usr_ware).unwrap())?;
+            let method = usr_translation_to_item_name.get(usr_method).unwrap();
+            let ware_production = ware.production_methods.iter().find(|(name, _)| name == method).map(|(_, production)| production)
+                                      .ok_or(DataError::UnknownProductionMethod(method.clone()))?;
+            match acc.iter_mut().find(|(id, ..)| id == &ware.info.ware_id) {
+                Some((_, _, _, productions)) => match productions.iter_mut().find(|(_, other)| other.method == ware_production.method) {
+                    Some((acc_count, _)) => *acc_count += count,
+                    None => productions.push((*count, ware_production)),
+                },
+                None => acc.push((ware.info.ware_id.clone(), 0f64, &ware.info, vec![(*count, ware_production)])),
+            }
+        }
+        let worker_consumption = worker_consumption(&self.wares, settings, &[], &[])?;
+        let consumed
+            = acc.iter()
+              .flat_map(|(ware_id, _, _, productions)| productions.iter().map(move |production| (ware_id, production)))
+              .flat_map(|(ware_id, (count, ware_production))| {
+                  ware_production.dependencies_per_minute().chain(module_workers_consumption(&self.wares, &worker_consumption, ware_id, &ware_production.method, settings))
+                      .map(move |(dependency, wares_per_minute)| (dependency.to_string(), wares_per_minute * *count as f64))
+              })
+              .collect::<Vec<_>>();
+        for (dependency, wares_per_minute) in consumed {
+            match acc.iter_mut().find(|(id, ..)| id == &dependency) {
+                Some((_, acc_wares_per_minute, ..)) => *acc_wares_per_minute += wares_per_minute,
+                None => acc.push((dependency.clone(), wares_per_minute, &self.wares.get(&dependency)?.info, Vec::new())),
+            }
+        }
+
+        let mut result = self.gen_planned_wares(acc, &HashMap::new(), settings);
+        // every module of the station is an existing one
+        for planned in result.iter_mut() {
+            planned.modules = ModuleCounts{existing: planned.modules.total, new: 0, total: planned.modules.total};
+        }
+        self.translate_planned_wares(&mut result);
+        Ok(result)
+    }
+    // Habitats for the workers of all the modules of plan rows, none without
+    // workers
+    pub fn calc_habitats<'a, It: Iterator<Item=&'a PlannedWare>> (&self, planned: It, settings: &PlanSettings) -> Result<Option<Habitats>, DataError> {
+        let settings = &self.untranslate_settings(settings)?;
+        let workers
+            = planned.filter_map(|planned| planned.method_id.as_ref().map(|method_id| planned.modules.total as f64 * self.wares.module_workers(&planned.single_ware.info.ware_id, method_id, settings)))
+              .sum::<f64>();
+        if 0. == workers {
+            return Ok(None);
+        }
+        let habitat
+            = match habitat_build_consumption(&self.wares, settings)? {
+                  Some((habitat, _)) => habitat,
+                  None => return Ok(None),
+              };
+        Ok(Some(Habitats {
+            workers,
+            ware_id:  habitat.module_ware.clone(),
+            habitat:  self.translations.get(&habitat.module_ware).unwrap_or(habitat.module_ware.clone()),
+            capacity: habitat.capacity,
+            habitats: (workers / habitat.capacity as f64).ceil() as usize,
+        }))
+    }
+    // Construction wares of (module ware id, count) at the price point
+    pub fn calc_build_cost (&self, modules: &[(String, usize)], price_point: PricePoint) -> Result<(Vec<BuildWare>, BuildTotal), DataError> {
+        let mut wares: Vec<BuildWare> = Vec::new();
+        let mut blueprints: Vec<&SingleWare> = Vec::new();
+        for (module_id, count) in modules.iter().filter(|(_, count)| 0 != *count) {
+            let module = self.wares.get(module_id)?;
+            if !blueprints.iter().any(|other| other.info.ware_id == module.info.ware_id) {
+                blueprints.push(module);
+            }
+            if let Some(production) = module.build_production() {
+                for (dependency, amount) in production.wares_dependencies.iter() {
+                    let amount = *amount as f64 / production.wares_per_cicle as f64 * *count as f64;
+                    match wares.iter_mut().find(|ware| &ware.ware == dependency) {
+                        Some(ware) => ware.amount += amount,
+                        None => wares.push(BuildWare{ware: dependency.clone(), amount, price: self.wares.get(dependency)?.info.price(price_point), credits: 0f64}),
+                    }
+                }
+            }
+        }
+        for ware in wares.iter_mut() {
+            ware.credits = ware.amount * ware.price;
+            self.translations.replace_if_exists(&mut ware.ware);
+        }
+        let wares_credits = wares.iter().map(|ware| ware.credits).sum::<f64>();
+        let blueprints_credits = blueprints.iter().map(|module| module.info.price_average as f64).sum::<f64>();
+        Ok((wares, BuildTotal{wares_credits, blueprints_credits, credits: wares_credits + blueprints_credits}))
+    }
+    // Storage of plan rows by transport, in the order of first appearance,
+    // empty without storage hours
+    pub fn calc_storage<'a, It: Iterator<Item=&'a PlannedWare>> (&self, planned: It) -> Vec<TransportStorage> {
+        let mut transports: Vec<TransportStorage> = Vec::new();
+        for (transport, storage) in planned.filter_map(|planned| planned.storage.as_ref().map(|storage| (&planned.single_ware.info.transport, storage))) {
+            let total
+                = match transports.iter_mut().position(|total| &total.transport == transport) {
+                      Some(idx) => &mut transports[idx],
+                      None => {
+                          transports.push(TransportStorage{transport: transport.clone(), input_volume: 0., intermediate_volume: 0., output_volume: 0., volume: 0., module: None, modules: None});
+                          transports.last_mut().unwrap()
+                      },
+                  };
+            total.input_volume += storage.input_volume;
+            total.intermediate_volume += storage.intermediate_volume;
+            total.output_volume += storage.output_volume;
+            total.volume += storage.input_volume + storage.intermediate_volume + storage.output_volume;
+        }
+        for total in transports.iter_mut() {
+            let largest
+                = self.wares.storages.iter()
+                  .filter(|storage| 0 != storage.capacity && storage.transports.contains(&total.transport))
+                  .fold(None, |largest: Option<&StorageModule>, storage| match largest {
+                      Some(other) if other.capacity >= storage.capacity => Some(other),
+                      _ => Some(storage),
+                  });
+            if let Some(storage) = largest {
+                let mut module = storage.module_ware.clone();
+                self.translations.replace_if_exists(&mut module);
+                total.module = Some(module);
+                total.modules = Some((total.volume / storage.capacity as f64).ceil() as usize);
+            }
+        }
+        transports
+    }
+    // Production trees of the requested outputs of plan rows, one per row with
+    // a requested part
+    pub fn calc_production_tree (&self, rows: &[&PlannedWare]) -> Vec<ProductionNode> {
+        let productions
+            = rows.iter()
+              .map(|PlannedWare{method_id, single_ware, ..}| method_id.as_ref().and_then(|method_id| {
+                  single_ware.production_methods.iter().find(|(id, _)| id == method_id).map(|(_, production)| production)
+              }))
+              .collect::<Vec<_>>();
+        let mut path = Vec::new();
+        rows.iter().enumerate()
+            .filter(|(_, row)| 0. < row.balance.requested_per_minute)
+            .map(|(idx, row)| production_node(rows, &productions, idx, row.balance.requested_per_minute, &mut path))
+            .collect()
+    }
+    // One row per (ware, method), the wares are not translated yet
+    fn gen_planned_wares (&self, acc: Vec<FabricsAccItem>, requested: &HashMap<String, f64>, settings: &PlanSettings) -> Vec<PlannedWare> {
+        let mut result = Vec::new();
+        for (ware, wares_per_minute, ware_info, productions) in acc {
+            let single_ware = self.wares.get(&ware_info.ware_id).unwrap();
+            let supply = settings.supply(&ware_info.ware_id);
+            if productions.is_empty() {
+                let balance = WareBalance::new(supply, wares_per_minute, requested.get(&ware_info.ware_id).copied().unwrap_or_default());
+                let economy_opt = settings.price.map(|price_point| WareEconomy::new(&single_ware.info, price_point, &balance, requested.get(&ware_info.ware_id).copied().unwrap_or_default(), supply, 0f64));
+                let storage_opt = settings.storage_hours.map(|storage_hours| WareStorage::new(&single_ware.info, storage_hours, &balance, requested.get(&ware_info.ware_id).copied().unwrap_or_default(), supply));
+                result.push(PlannedWare {
+                    ware,
+                    wares_per_minute,
+                    counts:      CountsOutput::Import,
+                    method_id:   None,
+                    single_ware: single_ware.clone(),
+                    balance,
+                    modules:     ModuleCounts{existing: 0, new: 0, total: 0},
+                    economy:     economy_opt,
+                    module:      None,
+                    storage:     storage_opt,
+                });
+                continue;
+            }
+            // existing modules stay, even those the plan doesn't need
+            let mut rows
+                = productions.into_iter()
+                  .map(|(fabrics_count, ware_production)| {
+                      let existing = settings.existing(&ware_info.ware_id).filter(|existing| existing.method == ware_production.method).map_or(0, |existing| existing.count);
+                      (ModuleCounts{existing, new: fabrics_count.saturating_sub(existing), total: fabrics_count.max(existing)}, ware_production)
+                  })
+                  .collect::<Vec<_>>();
+            // a method without modules gets a row only if supplies cover the ware
+            if rows.iter().any(|(modules, _)| 0 != modules.total) {
+                rows.retain(|(modules, _)| 0 != modules.total);
+            }
+            else {
+                rows.truncate(1);
+            }
 
-        let mut fabrics = Fabrics::new(&self.wares);
-        for (ware, desired_count) in desired_outputs {
-            match desired_count {
-                CountsInput::Fabrics(fabrics_count) => fabrics.add_fabrics(&ware, fabrics_count, &prioritylist, &blacklist)?,
-                CountsInput::WaresPerMinute(wares_per_minute) => fabrics.add_wares(&ware, wares_per_minute, &prioritylist, &blacklist)?,
+            // a ware made by several methods gets a row per method, its
+            // consumption and supplies are shared by the output
+            let produced_total = rows.iter().map(|(modules, ware_production)| ware_production.wares_per_minute_with_effects(settings) * modules.total as f64).sum::<f64>();
+            let rows_count = rows.len();
+            for (modules, ware_production) in rows {
+                let produced = ware_production.wares_per_minute_with_effects(settings) * modules.total as f64;
+                let share
+                    = if 0. == produced_total {
+                          1. / rows_count as f64
+                      }
+                      else {
+                          produced / produced_total
+                      };
+                let balance = WareBalance::new(produced + supply * share, wares_per_minute * share, requested.get(&ware_info.ware_id).copied().unwrap_or_default() * share);
+                let economy_opt
+                    = settings.price.map(|price_point| {
+                          // output minus inputs of a module at the same price point
+                          let inputs = ware_production.dependencies_per_minute().map(|(dependency, wares_per_minute)| wares_per_minute * self.wares.get(dependency).map_or(0f64, |ware| ware.info.price(price_point))).sum::<f64>();
+                          let value_added = ware_production.wares_per_minute_with_effects(settings) * single_ware.info.price(price_point) - inputs;
+                          WareEconomy::new(&single_ware.info, price_point, &balance, requested.get(&ware_info.ware_id).copied().unwrap_or_default() * share, supply * share, value_added)
+                      });
+                let storage_opt = settings.storage_hours.map(|storage_hours| WareStorage::new(&single_ware.info, storage_hours, &balance, requested.get(&ware_info.ware_id).copied().unwrap_or_default() * share, supply * share));
+                let module_opt
+                    = self.wares.find_production_module(&ware_info.ware_id, &ware_production.method)
+                      .map(|module| PlannedModule {
+                          ware_id:         module.info.ware_id.clone(),
+                          module:          module.info.ware_id.clone(),
+                          race:            self.wares.module_race(&module.info.ware_id, &ware_production.method),
+                          blueprint_price: module.info.price_average,
+                      });
+                result.push(PlannedWare {
+                    ware:             ware.clone(),
+                    wares_per_minute: wares_per_minute * share,
+                    counts:           CountsOutput::Produce(ware_production.method.clone(), modules.total),
+                    method_id:        Some(ware_production.method.clone()),
+                    single_ware:      single_ware.clone(),
+                    balance,
+                    modules,
+                    economy:          economy_opt,
+                    module:           module_opt,
+                    storage:          storage_opt,
+                });
             }
         }
-        let acc = fabrics.into_acc();
-        let mut result
-            = acc.into_iter()
-              .map(
-                  |(ware, wares_per_minute, ware_info, production_opt)| (
-                      ware,
-                      wares_per_minute,
-                      production_opt.map_or(
-                          CountsOutput::Import,
-                          |(fabrics_count, ware_production)|
-                            CountsOutput::Produce(
-                                ware_production.method.clone(),
-                                fabrics_count
-                                )
-                          ),
-                      self.wares.get(&ware_info.ware_id).unwrap().clone()
-                  )).collect::<Vec<_>>();
-
-        result.iter_mut()
+        result
+    }
+    fn translate_planned_wares (&self, planned: &mut [PlannedWare]) {
+        planned.iter_mut()
             .for_each(
-                |(ware_id, _, production_opt, _)| {
-                    self.translations.replace_if_exists(ware_id);
-                    if let CountsOutput::Produce(method, _) = production_opt {
+                |PlannedWare{ware, counts, module, ..}| {
+                    self.translations.replace_if_exists(ware);
+                    if let CountsOutput::Produce(method, _) = counts {
                         self.translations.replace_if_exists(method);
                     }
+                    if let Some(module) = module {
+                        self.translations.replace_if_exists(&mut module.module);
+                    }
                 });
-        Ok(result)
     }
 }
 
@@ -1007,113 +2907,113 @@ impl Data {
 mod tests {
     use super::*;
 
-const WARES1: &'static str =  r#"
+const WARES1: &str =  r#"
 <!-- line 195751 -->
-<languages>
-  <!-- English -->
-  <language id="44" name="English" voice="true" warning="A restart is required for changes to take effect!" />
-  <!-- German -->
-  <language id="49" name="Deutsch" voice="true" warning="Damit die ц└nderung wirksam wird, ist ein Neustart notwendig!" />
-  <!-- French -->
-  <language id="33" name="Franц╖ais" voice="true" warning="Un redц╘marrage est nц╘cessaire pour la prise en compte des changements !" />
-  <!-- Italian -->
-  <language id="39" name="Italiano" warning="I cambiamenti saranno effettivi al prossimo riavvio!" />
-  <!-- Russian -->
-  <language id="7" name="п═я┐я│я│п╨п╦п╧" voice="true" warning="п÷п╣я─п╣п╥п╟пЁя─я┐п╥п╦я┌п╣ п╦пЁя─я┐, я┤я┌п╬п╠я▀ п╦п╥п╪п╣п╫п╣п╫п╦я▐ п╡я│я┌я┐п©п╦п╩п╦ п╡ я│п╦п╩я┐!" />
-  <!-- Spanish -->
-  <language id="34" name="Espaц╠ol" warning="б║Es necesario reiniciar para que los cambios surtan efecto!" />
-  <!-- Portuguese (Brazil) -->
-  <language id="55" name="Portuguц╙s (Brasil)" warning="Para que as mudanц╖as funcionem, ц╘ preciso reiniciar!" />
-  <!-- Polish -->
-  <language id="48" name="Polski" warning="Wymagane jest ponowne uruchomienie, aby zmiany zadziaе┌aе┌y!" />
-  <!-- Simplified Chinese -->
-  <language id="86" name="Г╝─Д╫⌠Д╦╜Ф√┤" font="csfont" displaytimefactor="5.0" warning="И°─Х╕│И┤█Ф√╟Е░╞Е┼╗Д╩╔Д╫©Ф⌡╢Ф■╧Г■÷Ф∙┬О╪│" />
-  <!-- Traditional Chinese -->
-  <language id="88" name="Г╧│И╚■Д╦╜Ф√┤" font="ctfont" displaytimefactor="5.0" warning="И°─Х╕│И┤█Ф√╟Е∙÷Е▀∙Д╩╔Д╫©Ф⌡╢Ф■╧Г■÷Ф∙┬О╪│" />
-  <!-- Korean -->
-  <language id="82" name="М∙°Й╣╜Л√╢" font="kofont" displaytimefactor="5.0" warning="КЁ─Й╡╫К┌╢Л ╘Л²╢ Л═│Л ╘К░≤Й╦╟ Л°└М∙╢Л└°К┼■ Л┐┬К║° Л▀°Л·▒М∙≤Л┘■Л∙╪ М∙╘К▀┬К▀╓." />
-  <!-- Japanese -->
-  <language id="81" name="Ф≈╔Ф°╛Х╙·" font="jfont" displaytimefactor="5.0" warning="Е╓┴Ф⌡╢Ц┌▓Ф°┴Е┼╧Ц│╚Ц│≥Ц┌▀Ц│╚Ц│╞Е├█Х╣╥Е▀∙Ц│≈Ц│╕Ц│▐Ц│═Ц│∙Ц│└О╪│" />
-  <!-- Czech (not supported) -->
-  <!-- <language id="42" name="д█eе║tina" /> -->
-</languages>
+<languages>
+  <!-- English -->
+  <language id="44" name="English" voice="true" warning="A restart is required for changes to take effect!" />
+  <!-- German -->
+  <language id="49" name="Deutsch" voice="true" warning="Damit die ц└nderung wirksam wird, ist ein Neustart notwendig!" />
+  <!-- French -->
+  <language id="33" name="Franц╖ais" voice="true" warning="Un redц╘marrage est nц╘cessaire pour la prise en compte des changements !" />
+  <!-- Italian -->
+  <language id="39" name="Italiano" warning="I cambiamenti saranno effettivi al prossimo riavvio!" />
+  <!-- Russian -->
+  <language id="7" name="п═я┐я│я│п╨п╦п╧" voice="true" warning="п÷п╣я─п╣п╥п╟пЁя─я┐п╥п╦я┌п╣ п╦пЁя─я┐, я┤я┌п╬п╠я▀ п╦п╥п╪п╣п╫п╣п╫п╦я▐ п╡я│я┌я┐п©п╦п╩п╦ п╡ я│п╦п╩я┐!" />
+  <!-- Spanish -->
+  <language id="34" name="Espaц╠ol" warning="б║Es necesario reiniciar para que los cambios surtan efecto!" />
+  <!-- Portuguese (Brazil) -->
+  <language id="55" name="Portuguц╙s (Brasil)" warning="Para que as mudanц╖as funcionem, ц╘ preciso reiniciar!" />
+  <!-- Polish -->
+  <language id="48" name="Polski" warning="Wymagane jest ponowne uruchomienie, aby zmiany zadziaе┌aе┌y!" />
+  <!-- Simplified Chinese -->
+  <language id="86" name="Г╝─Д╫⌠Д╦╜Ф√┤" font="csfont" displaytimefactor="5.0" warning="И°─Х╕│И┤█Ф√╟Е░╞Е┼╗Д╩╔Д╫©Ф⌡╢Ф■╧Г■÷Ф∙┬О╪│" />
+  <!-- Traditional Chinese -->
+  <language id="88" name="Г╧│И╚■Д╦╜Ф√┤" font="ctfont" displaytimefactor="5.0" warning="И°─Х╕│И┤█Ф√╟Е∙÷Е▀∙Д╩╔Д╫©Ф⌡╢Ф■╧Г■÷Ф∙┬О╪│" />
+  <!-- Korean -->
+  <language id="82" name="М∙°Й╣╜Л√╢" font="kofont" displaytimefactor="5.0" warning="КЁ─Й╡╫К┌╢Л ╘Л²╢ Л═│Л ╘К░≤Й╦╟ Л°└М∙╢Л└°К┼■ Л┐┬К║° Л▀°Л·▒М∙≤Л┘■Л∙╪ М∙╘К▀┬К▀╓." />
+  <!-- Japanese -->
+  <language id="81" name="Ф≈╔Ф°╛Х╙·" font="jfont" displaytimefactor="5.0" warning="Е╓┴Ф⌡╢Ц┌▓Ф°┴Е┼╧Ц│╚Ц│≥Ц┌▀Ц│╚Ц│╞Е├█Х╣╥Е▀∙Ц│≈Ц│╕Ц│▐Ц│═Ц│∙Ц│└О╪│" />
+  <!-- Czech (not supported) -->
+  <!-- <language id="42" name="д█eе║tina" /> -->
+</languages>
 
 <!-- line 287816 -->
-<wares>
-  <production>
-    <method id="argon" name="{20206,201}">
-      <default race="argon" />
-    </method>
-    <method id="default" name="{20206,101}" />
-    <method id="paranid" name="{20206,301}">
-      <default race="paranid" />
-    </method>
-    <method id="processing" name="{20206,1301}" tags="noplayerbuild recycling" />
-    <method id="recycling" name="{20206,1101}" tags="noplayerbuild recycling" />
-    <method id="research" name="{20206,501}" tags="noplayerbuild" />
-    <method id="teladi" name="{20206,401}">
-      <default race="teladi" />
-    </method>
-    <method id="xenon" name="{20206,601}" tags="noplayerbuild">
-      <default race="xenon" />
-    </method>
-  </production>
-  <defaults id="default" name="default" transport="container" volume="1" tags="container">
-    <price min="1" average="1" max="1" />
-    <production time="10" amount="1" method="default" name="{20206,101}">
-      <effects>
-        <effect type="efficiency" product="1" />
-      </effects>
-    </production>
-    <container ref="sm_gen_pickup_container_01_macro" />
-    <icon active="ware_default" video="ware_noicon_macro" />
-  </defaults>
-
-  <ware id="energycells" name="{20201,701}" description="{20201,702}" factoryname="{20201,704}" group="energy" transport="container" volume="1" tags="container economy stationbuilding">
-    <price min="10" average="16" max="22" />
-    <production time="60" amount="175" method="default" name="{20206,101}">
-      <effects>
-        <effect type="sunlight" product="1" />
-        <effect type="work" product="0.43" />
-      </effects>
-    </production>
-    <icon active="ware_energycells" video="ware_energycells_macro" />
-  </ware>
-  <ware id="microchips" name="{20201,2201}" description="{20201,2202}" factoryname="{20201,2204}" group="hightech" transport="container" volume="22" tags="container economy">
-    <price min="805" average="948" max="1090" />
-    <production time="600" amount="72" method="default" name="{20206,101}">
-      <primary>
-        <ware ware="energycells" amount="50" />
-        <ware ware="siliconwafers" amount="200" />
-      </primary>
-      <effects>
-        <effect type="work" product="0.36" />
-      </effects>
-    </production>
-    <icon active="ware_microchips" video="ware_microchips_macro" />
-  </ware>
-  <ware id="silicon" name="{20201,3501}" description="{20201,3502}" factoryname="{20201,3504}" group="minerals" transport="solid" volume="10" tags="economy minable mineral solid">
-    <price min="111" average="130" max="150" />
-    <container ref="sm_gen_pickup_solid_01_macro" />
-    <icon active="ware_silicon" video="ware_silicon_macro" />
-  </ware>
-  <ware id="siliconwafers" name="{20201,3601}" description="{20201,3602}" factoryname="{20201,3604}" group="refined" transport="container" volume="18" tags="container economy">
-    <price min="180" average="299" max="419" />
-    <production time="180" amount="107" method="default" name="{20206,101}">
-      <primary>
-        <ware ware="energycells" amount="90" />
-        <ware ware="silicon" amount="240" />
-      </primary>
-      <effects>
-        <effect type="work" product="0.37" />
-      </effects>
-    </production>
-    <icon active="ware_siliconwafers" video="ware_siliconwafers_macro" />
-  </ware>
+<wares>
+  <production>
+    <method id="argon" name="{20206,201}">
+      <default race="argon" />
+    </method>
+    <method id="default" name="{20206,101}" />
+    <method id="paranid" name="{20206,301}">
+      <default race="paranid" />
+    </method>
+    <method id="processing" name="{20206,1301}" tags="noplayerbuild recycling" />
+    <method id="recycling" name="{20206,1101}" tags="noplayerbuild recycling" />
+    <method id="research" name="{20206,501}" tags="noplayerbuild" />
+    <method id="teladi" name="{20206,401}">
+      <default race="teladi" />
+    </method>
+    <method id="xenon" name="{20206,601}" tags="noplayerbuild">
+      <default race="xenon" />
+    </method>
+  </production>
+  <defaults id="default" name="default" transport="container" volume="1" tags="container">
+    <price min="1" average="1" max="1" />
+    <production time="10" amount="1" method="default" name="{20206,101}">
+      <effects>
+        <effect type="efficiency" product="1" />
+      </effects>
+    </production>
+    <container ref="sm_gen_pickup_container_01_macro" />
+    <icon active="ware_default" video="ware_noicon_macro" />
+  </defaults>
+
+  <ware id="energycells" name="{20201,701}" description="{20201,702}" factoryname="{20201,704}" group="energy" transport="container" volume="1" tags="container economy stationbuilding">
+    <price min="10" average="16" max="22" />
+    <production time="60" amount="175" method="default" name="{20206,101}">
+      <effects>
+        <effect type="sunlight" product="1" />
+        <effect type="work" product="0.43" />
+      </effects>
+    </production>
+    <icon active="ware_energycells" video="ware_energycells_macro" />
+  </ware>
+  <ware id="microchips" name="{20201,2201}" description="{20201,2202}" factoryname="{20201,2204}" group="hightech" transport="container" volume="22" tags="container economy">
+    <price min="805" average="948" max="1090" />
+    <production time="600" amount="72" method="default" name="{20206,101}">
+      <primary>
+        <ware ware="energycells" amount="50" />
+        <ware ware="siliconwafers" amount="200" />
+      </primary>
+      <effects>
+        <effect type="work" product="0.36" />
+      </effects>
+    </production>
+    <icon active="ware_microchips" video="ware_microchips_macro" />
+  </ware>
+  <ware id="silicon" name="{20201,3501}" description="{20201,3502}" factoryname="{20201,3504}" group="minerals" transport="solid" volume="10" tags="economy minable mineral solid">
+    <price min="111" average="130" max="150" />
+    <container ref="sm_gen_pickup_solid_01_macro" />
+    <icon active="ware_silicon" video="ware_silicon_macro" />
+  </ware>
+  <ware id="siliconwafers" name="{20201,3601}" description="{20201,3602}" factoryname="{20201,3604}" group="refined" transport="container" volume="18" tags="container economy">
+    <price min="180" average="299" max="419" />
+    <production time="180" amount="107" method="default" name="{20206,101}">
+      <primary>
+        <ware ware="energycells" amount="90" />
+        <ware ware="silicon" amount="240" />
+      </primary>
+      <effects>
+        <effect type="work" product="0.37" />
+      </effects>
+    </production>
+    <icon active="ware_siliconwafers" video="ware_siliconwafers_macro" />
+  </ware>
 </wares>
 "#;
 
-const TRANSLATIONS1: &'static str = r#"
+const TRANSLATIONS1: &str = r#"
 <!-- line 307812 -->
 <language id="44">
   <!-- line 355941 -->
@@ -1169,149 +3069,149 @@ const TRANSLATIONS1: &'static str = r#"
 </language>
 "#;
 
-const WARES2: &'static str = r#"
+const WARES2: &str = r#"
 <!-- line 195751 -->
-<languages>
-  <!-- English -->
-  <language id="44" name="English" voice="true" warning="A restart is required for changes to take effect!" />
-  <!-- German -->
-  <language id="49" name="Deutsch" voice="true" warning="Damit die ц└nderung wirksam wird, ist ein Neustart notwendig!" />
-  <!-- French -->
-  <language id="33" name="Franц╖ais" voice="true" warning="Un redц╘marrage est nц╘cessaire pour la prise en compte des changements !" />
-  <!-- Italian -->
-  <language id="39" name="Italiano" warning="I cambiamenti saranno effettivi al prossimo riavvio!" />
-  <!-- Russian -->
-  <language id="7" name="п═я┐я│я│п╨п╦п╧" voice="true" warning="п÷п╣я─п╣п╥п╟пЁя─я┐п╥п╦я┌п╣ п╦пЁя─я┐, я┤я┌п╬п╠я▀ п╦п╥п╪п╣п╫п╣п╫п╦я▐ п╡я│я┌я┐п©п╦п╩п╦ п╡ я│п╦п╩я┐!" />
-  <!-- Spanish -->
-  <language id="34" name="Espaц╠ol" warning="б║Es necesario reiniciar para que los cambios surtan efecto!" />
-  <!-- Portuguese (Brazil) -->
-  <language id="55" name="Portuguц╙s (Brasil)" warning="Para que as mudanц╖as funcionem, ц╘ preciso reiniciar!" />
-  <!-- Polish -->
-  <language id="48" name="Polski" warning="Wymagane jest ponowne uruchomienie, aby zmiany zadziaе┌aе┌y!" />
-  <!-- Simplified Chinese -->
-  <language id="86" name="Г╝─Д╫⌠Д╦╜Ф√┤" font="csfont" displaytimefactor="5.0" warning="И°─Х╕│И┤█Ф√╟Е░╞Е┼╗Д╩╔Д╫©Ф⌡╢Ф■╧Г■÷Ф∙┬О╪│" />
-  <!-- Traditional Chinese -->
-  <language id="88" name="Г╧│И╚■Д╦╜Ф√┤" font="ctfont" displaytimefactor="5.0" warning="И°─Х╕│И┤█Ф√╟Е∙÷Е▀∙Д╩╔Д╫©Ф⌡╢Ф■╧Г■÷Ф∙┬О╪│" />
-  <!-- Korean -->
-  <language id="82" name="М∙°Й╣╜Л√╢" font="kofont" displaytimefactor="5.0" warning="КЁ─Й╡╫К┌╢Л ╘Л²╢ Л═│Л ╘К░≤Й╦╟ Л°└М∙╢Л└°К┼■ Л┐┬К║° Л▀°Л·▒М∙≤Л┘■Л∙╪ М∙╘К▀┬К▀╓." />
-  <!-- Japanese -->
-  <language id="81" name="Ф≈╔Ф°╛Х╙·" font="jfont" displaytimefactor="5.0" warning="Е╓┴Ф⌡╢Ц┌▓Ф°┴Е┼╧Ц│╚Ц│≥Ц┌▀Ц│╚Ц│╞Е├█Х╣╥Е▀∙Ц│≈Ц│╕Ц│▐Ц│═Ц│∙Ц│└О╪│" />
-  <!-- Czech (not supported) -->
-  <!-- <language id="42" name="д█eе║tina" /> -->
-</languages>
+<languages>
+  <!-- English -->
+  <language id="44" name="English" voice="true" warning="A restart is required for changes to take effect!" />
+  <!-- German -->
+  <language id="49" name="Deutsch" voice="true" warning="Damit die ц└nderung wirksam wird, ist ein Neustart notwendig!" />
+  <!-- French -->
+  <language id="33" name="Franц╖ais" voice="true" warning="Un redц╘marrage est nц╘cessaire pour la prise en compte des changements !" />
+  <!-- Italian -->
+  <language id="39" name="Italiano" warning="I cambiamenti saranno effettivi al prossimo riavvio!" />
+  <!-- Russian -->
+  <language id="7" name="п═я┐я│я│п╨п╦п╧" voice="true" warning="п÷п╣я─п╣п╥п╟пЁя─я┐п╥п╦я┌п╣ п╦пЁя─я┐, я┤я┌п╬п╠я▀ п╦п╥п╪п╣п╫п╣п╫п╦я▐ п╡я│я┌я┐п©п╦п╩п╦ п╡ я│п╦п╩я┐!" />
+  <!-- Spanish -->
+  <language id="34" name="Espaц╠ol" warning="б║Es necesario reiniciar para que los cambios surtan efecto!" />
+  <!-- Portuguese (Brazil) -->
+  <language id="55" name="Portuguц╙s (Brasil)" warning="Para que as mudanц╖as funcionem, ц╘ preciso reiniciar!" />
+  <!-- Polish -->
+  <language id="48" name="Polski" warning="Wymagane jest ponowne uruchomienie, aby zmiany zadziaе┌aе┌y!" />
+  <!-- Simplified Chinese -->
+  <language id="86" name="Г╝─Д╫⌠Д╦╜Ф√┤" font="csfont" displaytimefactor="5.0" warning="И°─Х╕│И┤█Ф√╟Е░╞Е┼╗Д╩╔Д╫©Ф⌡╢Ф■╧Г■÷Ф∙┬О╪│" />
+  <!-- Traditional Chinese -->
+  <language id="88" name="Г╧│И╚■Д╦╜Ф√┤" font="ctfont" displaytimefactor="5.0" warning="И°─Х╕│И┤█Ф√╟Е∙÷Е▀∙Д╩╔Д╫©Ф⌡╢Ф■╧Г■÷Ф∙┬О╪│" />
+  <!-- Korean -->
+  <language id="82" name="М∙°Й╣╜Л√╢" font="kofont" displaytimefactor="5.0" warning="КЁ─Й╡╫К┌╢Л ╘Л²╢ Л═│Л ╘К░≤Й╦╟ Л°└М∙╢Л└°К┼■ Л┐┬К║° Л▀°Л·▒М∙≤Л┘■Л∙╪ М∙╘К▀┬К▀╓." />
+  <!-- Japanese -->
+  <language id="81" name="Ф≈╔Ф°╛Х╙·" font="jfont" displaytimefactor="5.0" warning="Е╓┴Ф⌡╢Ц┌▓Ф°┴Е┼╧Ц│╚Ц│≥Ц┌▀Ц│╚Ц│╞Е├█Х╣╥Е▀∙Ц│≈Ц│╕Ц│▐Ц│═Ц│∙Ц│└О╪│" />
+  <!-- Czech (not supported) -->
+  <!-- <language id="42" name="д█eе║tina" /> -->
+</languages>
 
 <!-- line 287816 -->
-<wares>
-  <production>
-    <method id="argon" name="{20206,201}">
-      <default race="argon" />
-    </method>
-    <method id="default" name="{20206,101}" />
-    <method id="paranid" name="{20206,301}">
-      <default race="paranid" />
-    </method>
-    <method id="processing" name="{20206,1301}" tags="noplayerbuild recycling" />
-    <method id="recycling" name="{20206,1101}" tags="noplayerbuild recycling" />
-    <method id="research" name="{20206,501}" tags="noplayerbuild" />
-    <method id="teladi" name="{20206,401}">
-      <default race="teladi" />
-    </method>
-    <method id="xenon" name="{20206,601}" tags="noplayerbuild">
-      <default race="xenon" />
-    </method>
-  </production>
-
-  <ware id="engine_arg_s_allround_01_mk1" name="{20107,1004}" description="{20107,1002}" group="engines" transport="equipment" volume="1" tags="engine equipment">
-    <price min="5526" average="6140" max="6754" />
-    <production time="10" amount="1" method="default" name="{20206,101}">
-      <primary>
-        <ware ware="energycells" amount="10" />
-        <ware ware="engineparts" amount="4" />
-      </primary>
-    </production>
-    <component ref="engine_arg_s_allround_01_mk1_macro" />
-    <restriction licence="generaluseequipment" />
-    <use threshold="0" />
-    <owner faction="alliance" />
-    <owner faction="antigone" />
-    <owner faction="argon" />
-    <owner faction="buccaneers" />
-    <owner faction="hatikvah" />
-  </ware>
-  <ware id="energycells" name="{20201,701}" description="{20201,702}" factoryname="{20201,704}" group="energy" transport="container" volume="1" tags="container economy stationbuilding">
-    <price min="10" average="16" max="22" />
-    <production time="60" amount="175" method="default" name="{20206,101}">
-      <effects>
-        <effect type="sunlight" product="1" />
-        <effect type="work" product="0.43" />
-      </effects>
-    </production>
-    <icon active="ware_energycells" video="ware_energycells_macro" />
-  </ware>
-  <ware id="engineparts" name="{20201,801}" description="{20201,802}" factoryname="{20201,804}" group="hightech" transport="container" volume="15" tags="container economy">
-    <price min="128" average="182" max="237" />
-    <production time="900" amount="208" method="default" name="{20206,101}">
-      <primary>
-        <ware ware="antimattercells" amount="80" />
-        <ware ware="energycells" amount="60" />
-        <ware ware="refinedmetals" amount="96" />
-      </primary>
-      <effects>
-        <effect type="work" product="0.47" />
-      </effects>
-    </production>
-    <production time="900" amount="208" method="teladi" name="{20206,401}">
-      <primary>
-        <ware ware="antimattercells" amount="80" />
-        <ware ware="energycells" amount="60" />
-        <ware ware="teladianium" amount="70" />
-      </primary>
-      <effects>
-        <effect type="work" product="0.47" />
-      </effects>
-    </production>
-    <icon active="ware_engineparts" video="ware_engineparts_macro" />
-  </ware>
-  <ware id="antimattercells" name="{20201,201}" description="{20201,202}" factoryname="{20201,204}" group="refined" transport="container" volume="18" tags="container economy">
-    <price min="121" average="202" max="282" />
-    <production time="120" amount="99" method="default" name="{20206,101}">
-      <primary>
-        <ware ware="energycells" amount="100" />
-        <ware ware="hydrogen" amount="320" />
-      </primary>
-      <effects>
-        <effect type="work" product="0.35" />
-      </effects>
-    </production>
-    <icon active="ware_antimattercells" video="ware_antimattercells_macro" />
-  </ware>
-  <ware id="hydrogen" name="{20201,1301}" description="{20201,1302}" factoryname="{20201,1304}" group="gases" transport="liquid" volume="6" tags="economy gas liquid minable">
-    <price min="49" average="58" max="67" />
-    <container ref="sm_gen_pickup_liquid_01_macro" />
-    <icon active="ware_hydrogen" video="ware_hydrogen_macro" />
-  </ware>
-  <ware id="refinedmetals" name="{20201,3201}" description="{20201,3202}" factoryname="{20201,3204}" group="refined" transport="container" volume="14" tags="container economy">
-    <price min="89" average="148" max="207" />
-    <production time="150" amount="88" method="default" name="{20206,101}">
-      <primary>
-        <ware ware="energycells" amount="90" />
-        <ware ware="ore" amount="240" />
-      </primary>
-      <effects>
-        <effect type="work" product="0.43" />
-      </effects>
-    </production>
-    <icon active="ware_refinedmetals" video="ware_refinedmetals_macro" />
-  </ware>
-  <ware id="ore" name="{20201,2701}" description="{20201,2702}" factoryname="{20201,2704}" group="minerals" transport="solid" volume="10" tags="economy minable mineral solid">
-    <price min="43" average="50" max="58" />
-    <container ref="sm_gen_pickup_solid_01_macro" />
-    <icon active="ware_ore" video="ware_ore_macro" />
-  </ware>
+<wares>
+  <production>
+    <method id="argon" name="{20206,201}">
+      <default race="argon" />
+    </method>
+    <method id="default" name="{20206,101}" />
+    <method id="paranid" name="{20206,301}">
+      <default race="paranid" />
+    </method>
+    <method id="processing" name="{20206,1301}" tags="noplayerbuild recycling" />
+    <method id="recycling" name="{20206,1101}" tags="noplayerbuild recycling" />
+    <method id="research" name="{20206,501}" tags="noplayerbuild" />
+    <method id="teladi" name="{20206,401}">
+      <default race="teladi" />
+    </method>
+    <method id="xenon" name="{20206,601}" tags="noplayerbuild">
+      <default race="xenon" />
+    </method>
+  </production>
+
+  <ware id="engine_arg_s_allround_01_mk1" name="{20107,1004}" description="{20107,1002}" group="engines" transport="equipment" volume="1" tags="engine equipment">
+    <price min="5526" average="6140" max="6754" />
+    <production time="10" amount="1" method="default" name="{20206,101}">
+      <primary>
+        <ware ware="energycells" amount="10" />
+        <ware ware="engineparts" amount="4" />
+      </primary>
+    </production>
+    <component ref="engine_arg_s_allround_01_mk1_macro" />
+    <restriction licence="generaluseequipment" />
+    <use threshold="0" />
+    <owner faction="alliance" />
+    <owner faction="antigone" />
+    <owner faction="argon" />
+    <owner faction="buccaneers" />
+    <owner faction="hatikvah" />
+  </ware>
+  <ware id="energycells" name="{20201,701}" description="{20201,702}" factoryname="{20201,704}" group="energy" transport="container" volume="1" tags="container economy stationbuilding">
+    <price min="10" average="16" max="22" />
+    <production time="60" amount="175" method="default" name="{20206,101}">
+      <effects>
+        <effect type="sunlight" product="1" />
+        <effect type="work" product="0.43" />
+      </effects>
+    </production>
+    <icon active="ware_energycells" video="ware_energycells_macro" />
+  </ware>
+  <ware id="engineparts" name="{20201,801}" description="{20201,802}" factoryname="{20201,804}" group="hightech" transport="container" volume="15" tags="container economy">
+    <price min="128" average="182" max="237" />
+    <production time="900" amount="208" method="default" name="{20206,101}">
+      <primary>
+        <ware ware="antimattercells" amount="80" />
+        <ware ware="energycells" amount="60" />
+        <ware ware="refinedmetals" amount="96" />
+      </primary>
+      <effects>
+        <effect type="work" product="0.47" />
+      </effects>
+    </production>
+    <production time="900" amount="208" method="teladi" name="{20206,401}">
+      <primary>
+        <ware ware="antimattercells" amount="80" />
+        <ware ware="energycells" amount="60" />
+        <ware ware="teladianium" amount="70" />
+      </primary>
+      <effects>
+        <effect type="work" product="0.47" />
+      </effects>
+    </production>
+    <icon active="ware_engineparts" video="ware_engineparts_macro" />
+  </ware>
+  <ware id="antimattercells" name="{20201,201}" description="{20201,202}" factoryname="{20201,204}" group="refined" transport="container" volume="18" tags="container economy">
+    <price min="121" average="202" max="282" />
+    <production time="120" amount="99" method="default" name="{20206,101}">
+      <primary>
+        <ware ware="energycells" amount="100" />
+        <ware ware="hydrogen" amount="320" />
+      </primary>
+      <effects>
+        <effect type="work" product="0.35" />
+      </effects>
+    </production>
+    <icon active="ware_antimattercells" video="ware_antimattercells_macro" />
+  </ware>
+  <ware id="hydrogen" name="{20201,1301}" description="{20201,1302}" factoryname="{20201,1304}" group="gases" transport="liquid" volume="6" tags="economy gas liquid minable">
+    <price min="49" average="58" max="67" />
+    <container ref="sm_gen_pickup_liquid_01_macro" />
+    <icon active="ware_hydrogen" video="ware_hydrogen_macro" />
+  </ware>
+  <ware id="refinedmetals" name="{20201,3201}" description="{20201,3202}" factoryname="{20201,3204}" group="refined" transport="container" volume="14" tags="container economy">
+    <price min="89" average="148" max="207" />
+    <production time="150" amount="88" method="default" name="{20206,101}">
+      <primary>
+        <ware ware="energycells" amount="90" />
+        <ware ware="ore" amount="240" />
+      </primary>
+      <effects>
+        <effect type="work" product="0.43" />
+      </effects>
+    </production>
+    <icon active="ware_refinedmetals" video="ware_refinedmetals_macro" />
+  </ware>
+  <ware id="ore" name="{20201,2701}" description="{20201,2702}" factoryname="{20201,2704}" group="minerals" transport="solid" volume="10" tags="economy minable mineral solid">
+    <price min="43" average="50" max="58" />
+    <container ref="sm_gen_pickup_solid_01_macro" />
+    <icon active="ware_ore" video="ware_ore_macro" />
+  </ware>
 </wares>
 "#;
 
-const TRANSLATIONS2: &'static str = r#"
+const TRANSLATIONS2: &str = r#"
 <!-- line 307812 -->
 <language id="44">
   <!-- line 355941 -->
@@ -1397,11 +3297,799 @@ const TRANSLATIONS2: &'static str = r#"
         println!("## Done Wares = {:?}", wares);
         let translations = Translations::load_from_string(translations, &wares, langs, "local".into()).unwrap();
         println!("## Done Translations = {:?}", translations);
-        let data = Data{wares, translations};
-        let calced = data.calc_required_fabric_counts(vec![("Microchips".to_string(), CountsInput::WaresPerMinute(36f64))], Vec::new(), Vec::new()).unwrap();
+        let data = Data{wares, translations, sources: Vec::new()};
+        let calced = data.calc_required_fabric_counts(vec![("Microchips".to_string(), CountsInput::WaresPerMinute(36f64))], Vec::new(), Vec::new(), &PlanSettings::default()).unwrap();
         println!("## Calced = {:?}", calced);
     }
     #[test]
+    fn test_production_effects () {
+        let (wares, langs) = Wares::load_wares_translationids_and_productionmethods_from_string(WARES1.to_string(), "local".into()).unwrap();
+        let translations = Translations::load_from_string(TRANSLATIONS1.to_string(), &wares, langs, "local".into()).unwrap();
+        let data = Data{wares, translations, sources: Vec::new()};
+        let energycells = data.wares.get("energycells").unwrap().production_methods[0].1.clone();
+        assert_eq!(energycells.effects, vec![("sunlight".to_string(), 1f64), ("work".to_string(), 0.43f64)]);
+
+        // 175 per module, 250.25 when fully staffed
+        let request = || vec![("Energy Cells".to_string(), CountsInput::WaresPerMinute(250f64))];
+        let count = |settings: &PlanSettings| match &data.calc_required_fabric_counts(request(), Vec::new(), Vec::new(), settings).unwrap()[0].counts {
+                        CountsOutput::Produce(_, count) => *count,
+                        CountsOutput::Import => panic!("energy cells are produced"),
+                    };
+        assert_eq!(count(&PlanSettings::default()), 2);
+        assert_eq!(count(&PlanSettings{workforce_percent: 100., ..Default::default()}), 1);
+        assert_eq!(count(&PlanSettings{sunlight_percent: 50., ..Default::default()}), 3);
+        assert!(data.calc_required_fabric_counts(request(), Vec::new(), Vec::new(), &PlanSettings{workforce_percent: 120., ..Default::default()}).is_err());
+        // no sunlight stops solar power instead of needing endless modules
+        assert!(data.calc_required_fabric_counts(request(), Vec::new(), Vec::new(), &PlanSettings{sunlight_percent: 0., ..Default::default()}).is_err());
+        assert!(data.calc_required_fabric_counts(request(), Vec::new(), Vec::new(), &PlanSettings{sunlight_percent: f64::NAN, ..Default::default()}).is_err());
+    }
+    #[test]
+    fn test_ware_metadata () {
+        let (wares, langs) = Wares::load_wares_translationids_and_productionmethods_from_string(WARES1.to_string(), "local".into()).unwrap();
+        let translations = Translations::load_from_string(TRANSLATIONS1.to_string(), &wares, langs, "local".into()).unwrap();
+        let data = Data{wares, translations, sources: Vec::new()};
+        let info = &data.wares.get("microchips").unwrap().info;
+        assert_eq!(info.group.as_deref(), Some("hightech"));
+        assert_eq!(info.volume, 22);
+        assert!(info.price_min <= info.price_average && info.price_average <= info.price_max);
+        assert_eq!(info.tags, vec!["container".to_string(), "economy".to_string()]);
+        assert_eq!(data.get_ware_factoryname("microchips").as_deref(), Some("Microchip Factory"));
+        assert!(data.get_ware_description("energycells").unwrap().contains("(or bi"));
+        assert!(data.calc_required_fabric_counts(vec![("Microchip Factory".to_string(), CountsInput::Fabrics(1))], Vec::new(), Vec::new(), &PlanSettings::default()).is_err());
+    }
+    #[test]
+    fn test_noplayerbuild_methods () {
+        let wares = WARES1
+                        .replace(r#"<production time="600" amount="72" method="default""#,
+                                 r#"<production time="60" amount="72" method="xenon" name="{20206,601}" />
+                                    <production time="600" amount="72" method="default""#)
+                        .replace(r#"<production time="180" amount="107" method="default" name="{20206,101}">"#,
+                                 r#"<production time="180" amount="107" method="research" name="{20206,501}">"#);
+        let data = Data::load_data_str(wares, TRANSLATIONS1.to_string()).unwrap();
+        assert_eq!(data.gen_production_methods_list(), vec!["argon", "default", "paranid", "processing", "recycling", "research", "teladi", "xenon"]);
+        assert!(!data.get_production_methods().iter().find(|method| method.id == "xenon").unwrap().is_player_buildable());
+        assert_eq!(data.get_production_methods().iter().find(|method| method.id == "teladi").unwrap().default_races, vec!["teladi"]);
+
+        let request = || vec![("Microchips".to_string(), CountsInput::WaresPerMinute(36f64))];
+        let methods = |calced: Vec<PlannedWare>| calced.into_iter().map(|PlannedWare{ware, counts, ..}| (ware, match counts { CountsOutput::Produce(method, _) => method, CountsOutput::Import => "Import".to_string() })).collect::<Vec<_>>();
+
+        let calced = methods(data.calc_required_fabric_counts(request(), Vec::new(), Vec::new(), &PlanSettings::default()).unwrap());
+        assert!(calced.contains(&("Microchips".to_string(), "Universal".to_string())));
+        assert!(calced.contains(&("Silicon Wafers".to_string(), "Import".to_string())));
+
+        let calced = methods(data.calc_required_fabric_counts(request(), vec!["Xenon".to_string()], Vec::new(), &PlanSettings::default()).unwrap());
+        assert!(calced.contains(&("Microchips".to_string(), "Xenon".to_string())));
+
+        let calced = methods(data.calc_required_fabric_counts(request(), vec!["Universal".to_string()], Vec::new(), &PlanSettings{allow_noplayerbuild: true, ..Default::default()}).unwrap());
+        assert!(calced.contains(&("Silicon Wafers".to_string(), "Research".to_string())));
+    }
+    #[test]
+    fn test_ware_defaults () {
+        let wares = WARES1.replace("</wares>", r#"<ware id="spacefuel" name="{20201,4001}">
+                                                     <production amount="10">
+                                                       <primary>
+                                                         <ware ware="energycells" amount="10" />
+                                                       </primary>
+                                                     </production>
+                                                   </ware>
+                                                 </wares>"#);
+        let (wares, _) = Wares::load_wares_translationids_and_productionmethods_from_string(wares, "local".into()).unwrap();
+        let spacefuel = wares.get("spacefuel").unwrap();
+        assert_eq!(spacefuel.info.transport, "container");
+        assert_eq!(spacefuel.info.volume, 1);
+        assert_eq!(spacefuel.info.tags, vec!["container"]);
+        assert_eq!((spacefuel.info.price_min, spacefuel.info.price_average, spacefuel.info.price_max), (1, 1, 1));
+        let (method, production) = &spacefuel.production_methods[0];
+        assert_eq!(method, "default");
+        assert_eq!((production.cicle_seconds, production.wares_per_cicle), (10f64, 10));
+        assert_eq!(production.translation.id, "101");
+        // silicon has no production and must not get one from defaults
+        assert!(wares.get("silicon").unwrap().production_methods.is_empty());
+    }
+    // WARES1 with a teladi microchips method, twice the output and inputs of
+    // the default one
+    fn wares_with_teladi_microchips () -> String {
+        WARES1.replace(r#"<production time="600" amount="72" method="default""#,
+                       r#"<production time="600" amount="144" method="teladi" name="{20206,401}">
+                            <primary>
+                              <ware ware="energycells" amount="100" />
+                              <ware ware="siliconwafers" amount="400" />
+                            </primary>
+                          </production>
+                          <production time="600" amount="72" method="default""#)
+    }
+    #[test]
+    fn test_solver () {
+        // teladi microchips need a half of modules, but these are expensive
+        let wares = wares_with_teladi_microchips()
+                        .replace("</wares>", r#"<ware id="module_gen_prod_microchips_01" transport="container" volume="1"><price min="1000" average="1000" max="1000" /></ware>
+                                               <ware id="module_tel_prod_microchips_01" transport="container" volume="1"><price min="9000" average="9000" max="9000" /></ware>
+                                               <ware id="module_gen_prod_energycells_01" transport="container" volume="1"><price min="100" average="100" max="100" /></ware>
+                                               <ware id="module_gen_prod_siliconwafers_01" transport="container" volume="1"><price min="100" average="100" max="100" /></ware>
+                                             </wares>"#);
+        let data = Data::load_data_str(wares, TRANSLATIONS1.to_string()).unwrap();
+        let request = || vec![("Microchips".to_string(), CountsInput::WaresPerMinute(36f64))];
+        let modules = |calced: &[PlannedWare]| calced.iter().map(|PlannedWare{counts, ..}| match counts { CountsOutput::Produce(_, count) => *count, CountsOutput::Import => 0 }).sum::<usize>();
+        let microchips_method = |calced: &[PlannedWare]| calced.iter().find_map(|PlannedWare{ware, counts, ..}| match counts { CountsOutput::Produce(method, _) if ware == "Microchips" => Some(method.clone()), _ => None }).unwrap();
+
+        let greedy = data.calc_required_fabric_counts(request(), Vec::new(), Vec::new(), &PlanSettings::default()).unwrap();
+        let by_count = data.calc_required_fabric_counts(request(), Vec::new(), Vec::new(), &PlanSettings{solver: Some(SolverObjective::ModuleCount), ..Default::default()}).unwrap();
+        let by_cost = data.calc_required_fabric_counts(request(), Vec::new(), Vec::new(), &PlanSettings{solver: Some(SolverObjective::BuildCost), ..Default::default()}).unwrap();
+        assert!(modules(&by_count) <= modules(&greedy));
+        assert!(by_count.iter().any(|PlannedWare{ware, counts, ..}| ware == "Microchips" && matches!(counts, CountsOutput::Produce(method, _) if method == "Teladi")));
+        assert_eq!(microchips_method(&by_cost), "Universal");
+        // 36 per minute is 3 teladi modules of 14.4 or 5 universal of 7.2
+        // (or a mix, a ware may get a row per method)
+        let microchips_count = |calced: &[PlannedWare]| calced.iter().filter_map(|PlannedWare{ware, counts, ..}| match counts { CountsOutput::Produce(_, count) if ware == "Microchips" => Some(*count), _ => None }).sum::<usize>();
+        assert_eq!(microchips_count(&by_count), 3);
+        assert_eq!(microchips_count(&by_cost), 5);
+
+        let data = Data::load_data_str(WARES1.to_string(), TRANSLATIONS1.to_string()).unwrap();
+        assert!(matches!(data.calc_required_fabric_counts(request(), Vec::new(), Vec::new(), &PlanSettings{solver: Some(SolverObjective::BuildCost), ..Default::default()}), Err(DataError::ModuleNotFound(..))));
+    }
+    #[test]
+    fn test_ware_balance () {
+        let data = Data::load_data_str(WARES1.to_string(), TRANSLATIONS1.to_string()).unwrap();
+        let calced = data.calc_required_fabric_counts(vec![("Microchips".to_string(), CountsInput::WaresPerMinute(30f64))], Vec::new(), Vec::new(), &PlanSettings::default()).unwrap();
+        let balance = |name: &str| calced.iter().find(|PlannedWare{ware, ..}| ware == name).unwrap().balance.clone();
+        // 5 modules of 7.2 per minute
+        assert_eq!(balance("Microchips"), WareBalance::new(36., 30., 30.));
+        assert!((balance("Microchips").utilization_percent - 100. * 30. / 36.).abs() < 1e-9);
+        for PlannedWare{wares_per_minute, counts, balance, ..} in calced.iter() {
+            assert_eq!(balance.consumed_per_minute, *wares_per_minute);
+            match counts {
+                CountsOutput::Produce(..) => assert!(0. <= balance.surplus_per_minute),
+                CountsOutput::Import => assert_eq!(balance.surplus_per_minute, -wares_per_minute),
+            }
+        }
+    }
+    #[test]
+    fn test_existing_modules () {
+        let data = Data::load_data_str(WARES1.to_string(), TRANSLATIONS1.to_string()).unwrap();
+        let request = || vec![("Microchips".to_string(), CountsInput::WaresPerMinute(30f64))];
+        let existing = |count| ExistingModules{ware: "Microchips".into(), method: "Universal".into(), count};
+        let find = |calced: &[PlannedWare], name: &str| calced.iter().find(|PlannedWare{ware, ..}| ware == name).unwrap().clone();
+
+        // 5 modules are needed
+        let calced = data.calc_required_fabric_counts(request(), Vec::new(), Vec::new(), &PlanSettings{existing_modules: vec![existing(2)], ..Default::default()}).unwrap();
+        assert_eq!(find(&calced, "Microchips").modules, ModuleCounts{existing: 2, new: 3, total: 5});
+        assert!(matches!(find(&calced, "Microchips").counts, CountsOutput::Produce(_, 5)));
+        // spare existing modules add surplus but not demand
+        let greedy = data.calc_required_fabric_counts(request(), Vec::new(), Vec::new(), &PlanSettings::default()).unwrap();
+        let calced = data.calc_required_fabric_counts(request(), Vec::new(), Vec::new(), &PlanSettings{existing_modules: vec![existing(7)], ..Default::default()}).unwrap();
+        assert_eq!(find(&calced, "Microchips").modules, ModuleCounts{existing: 7, new: 0, total: 7});
+        assert!((find(&calced, "Microchips").balance.produced_per_minute - 7. * 7.2).abs() < 1e-9);
+        assert_eq!(find(&calced, "Silicon Wafers").wares_per_minute, find(&greedy, "Silicon Wafers").wares_per_minute);
+        let calced = data.calc_required_fabric_counts(request(), Vec::new(), Vec::new(), &PlanSettings{existing_modules: vec![existing(2)], solver: Some(SolverObjective::ModuleCount), ..Default::default()}).unwrap();
+        assert_eq!(find(&calced, "Microchips").modules, ModuleCounts{existing: 2, new: 3, total: 5});
+
+        // supplies cover the demand before modules
+        let supplies = vec![ExternalSupply{ware: "Silicon Wafers".into(), wares_per_minute: 1000.}];
+        for solver in [None, Some(SolverObjective::ModuleCount)] {
+            let calced = data.calc_required_fabric_counts(request(), Vec::new(), Vec::new(), &PlanSettings{external_supplies: supplies.clone(), solver, ..Default::default()}).unwrap();
+            let wafers = find(&calced, "Silicon Wafers");
+            assert_eq!(wafers.modules.total, 0);
+            assert_eq!(wafers.balance.produced_per_minute, 1000.);
+            assert!(calced.iter().all(|PlannedWare{ware, ..}| ware != "Silicon"));
+        }
+
+        assert!(matches!(data.calc_required_fabric_counts(request(), Vec::new(), Vec::new(), &PlanSettings{existing_modules: vec![existing(1), existing(2)], ..Default::default()}), Err(DataError::InconsistentRequest(_))));
+    }
+    #[test]
+    fn test_recipe_cycles () {
+        // silicon wafers take microchips back
+        let cycled = |microchips: u32| WARES1.replace(r#"<ware ware="silicon" amount="240" />"#, &format!(r#"<ware ware="silicon" amount="240" /><ware ware="microchips" amount="{}" />"#, microchips));
+        let request = || vec![("Microchips".to_string(), CountsInput::WaresPerMinute(30f64))];
+
+        let data = Data::load_data_str(cycled(1), TRANSLATIONS1.to_string()).unwrap();
+        match data.calc_required_fabric_counts(request(), Vec::new(), Vec::new(), &PlanSettings::default()) {
+            Err(DataError::RecipeCycle(cycle)) => assert_eq!(cycle, vec!["microchips", "siliconwafers", "microchips"]),
+            other => panic!("expected a cycle, got {:?}", other.map(|_| ())),
+        }
+        for solver in [None, Some(SolverObjective::ModuleCount)] {
+            let calced = data.calc_required_fabric_counts(request(), Vec::new(), Vec::new(), &PlanSettings{solve_cycles: true, solver, ..Default::default()}).unwrap();
+            let PlannedWare{wares_per_minute, balance, ..} = calced.iter().find(|PlannedWare{ware, ..}| ware == "Microchips").unwrap();
+            assert!(30. < *wares_per_minute);
+            for PlannedWare{counts, balance, ..} in calced.iter() {
+                if let CountsOutput::Produce(..) = counts {
+                    assert!(0. <= balance.surplus_per_minute);
+                }
+            }
+            assert!(0. <= balance.surplus_per_minute);
+        }
+
+        // every wafers module takes more microchips than its wafers make
+        let data = Data::load_data_str(cycled(107), TRANSLATIONS1.to_string()).unwrap();
+        assert!(matches!(data.calc_required_fabric_counts(request(), Vec::new(), Vec::new(), &PlanSettings{solve_cycles: true, ..Default::default()}), Err(DataError::RecipeCycle(_))));
+    }
+    #[test]
+    fn test_ware_methods () {
+        let wares = wares_with_teladi_microchips();
+        let data = Data::load_data_str(wares, TRANSLATIONS1.to_string()).unwrap();
+        let request = || vec![("Microchips".to_string(), CountsInput::WaresPerMinute(36f64))];
+        let prioritylist = || vec!["Teladi".to_string()];
+        let microchips_method = |calced: Vec<PlannedWare>| calced.into_iter().find_map(|PlannedWare{ware, counts, ..}| match counts { CountsOutput::Produce(method, _) if ware == "Microchips" => Some(method), _ => None }).unwrap();
+        let settings = |method: Option<&str>, blacklist: &[&str], solver| PlanSettings {
+            ware_methods: vec![WareMethods{ware: "Microchips".into(), method: method.map(String::from), blacklist: blacklist.iter().map(|method| method.to_string()).collect(), ..Default::default()}],
+            solver,
+            ..Default::default()
+        };
+
+        assert_eq!(microchips_method(data.calc_required_fabric_counts(request(), prioritylist(), Vec::new(), &PlanSettings::default()).unwrap()), "Teladi");
+        for solver in [None, Some(SolverObjective::ModuleCount)] {
+            // the ware's own lists go ahead of the global ones
+            assert_eq!(microchips_method(data.calc_required_fabric_counts(request(), prioritylist(), Vec::new(), &settings(None, &["Teladi"], solver)).unwrap()), "Universal");
+            assert_eq!(microchips_method(data.calc_required_fabric_counts(request(), prioritylist(), Vec::new(), &settings(Some("Universal"), &[], solver)).unwrap()), "Universal");
+            assert_eq!(microchips_method(data.calc_required_fabric_counts(request(), Vec::new(), vec!["Teladi".to_string()], &settings(Some("Teladi"), &[], solver)).unwrap()), "Teladi");
+        }
+        // a row without production only carries the methods of its ware
+        let calced = data.calc_required_fabric_counts(vec![("Microchips".to_string(), CountsInput::WaresPerMinute(0f64)), ("Silicon Wafers".to_string(), CountsInput::WaresPerMinute(10f64))], Vec::new(), Vec::new(), &settings(Some("Teladi"), &[], None)).unwrap();
+        assert!(calced.iter().all(|PlannedWare{ware, ..}| ware != "Microchips"));
+
+        assert!(matches!(data.calc_required_fabric_counts(request(), Vec::new(), Vec::new(), &settings(Some("Teladi"), &["Teladi"], None)), Err(DataError::PriorityBlackListsIntersection(_))));
+        assert!(matches!(data.calc_required_fabric_counts(request(), Vec::new(), Vec::new(), &settings(Some("Paranid"), &[], None)), Err(DataError::UnknownProductionMethod(_))));
+        assert!(matches!(data.calc_required_fabric_counts(request(), Vec::new(), Vec::new(), &settings(None, &["Teladi", "Universal"], None)), Err(DataError::AllMethodsBlacklisted(_))));
+    }
+    #[test]
+    fn test_method_split () {
+        let wares = wares_with_teladi_microchips();
+        let data = Data::load_data_str(wares, TRANSLATIONS1.to_string()).unwrap();
+        let request = || vec![("Microchips".to_string(), CountsInput::WaresPerMinute(36f64))];
+        let settings = |split: &[(&str, MethodShare)], solver| PlanSettings {
+            ware_methods: vec![WareMethods{ware: "Microchips".into(), split: split.iter().map(|(method, share)| MethodSplit{method: method.to_string(), share: *share}).collect(), ..Default::default()}],
+            solver,
+            ..Default::default()
+        };
+        let microchips = |calced: &[PlannedWare]| calced.iter().filter(|PlannedWare{ware, ..}| ware == "Microchips").map(|PlannedWare{wares_per_minute, counts, ..}| (counts.clone(), *wares_per_minute)).collect::<Vec<_>>();
+        let counts = |calced: &[PlannedWare]| microchips(calced).into_iter().map(|(counts, _)| format!("{:?}", counts)).collect::<Vec<_>>();
+
+        // 18 per minute each, by modules of 7.2 and 14.4
+        for solver in [None, Some(SolverObjective::ModuleCount)] {
+            let calced = data.calc_required_fabric_counts(request(), Vec::new(), Vec::new(), &settings(&[("Universal", MethodShare::Ratio(1.)), ("Teladi", MethodShare::Ratio(1.))], solver)).unwrap();
+            assert_eq!(counts(&calced), vec!["Produce(\"Universal\", 3)", "Produce(\"Teladi\", 2)"]);
+            // the consumption is shared by the output
+            assert!((microchips(&calced).iter().map(|(_, wares_per_minute)| wares_per_minute).sum::<f64>() - 36.).abs() < 1e-9);
+            assert!((microchips(&calced)[0].1 - 36. * 21.6 / 50.4).abs() < 1e-9);
+            let wafers = calced.iter().find(|PlannedWare{ware, ..}| ware == "Silicon Wafers").unwrap();
+            assert!((wafers.wares_per_minute - (3. * 20. + 2. * 40.)).abs() < 1e-9);
+        }
+        // the rest of a fixed count goes to the usual method, merged if the same
+        let calced = data.calc_required_fabric_counts(request(), vec!["Universal".to_string()], Vec::new(), &settings(&[("Teladi", MethodShare::Fabrics(1))], None)).unwrap();
+        assert_eq!(counts(&calced), vec!["Produce(\"Teladi\", 1)", "Produce(\"Universal\", 3)"]);
+        let calced = data.calc_required_fabric_counts(request(), Vec::new(), Vec::new(), &settings(&[("Teladi", MethodShare::Fabrics(1))], None)).unwrap();
+        assert_eq!(counts(&calced), vec!["Produce(\"Teladi\", 3)"]);
+
+        assert!(matches!(data.calc_required_fabric_counts(request(), Vec::new(), Vec::new(), &settings(&[("Teladi", MethodShare::Ratio(0.))], None)), Err(DataError::InconsistentRequest(_))));
+        assert_eq!("Ratio(0.5)".parse::<MethodShare>(), Ok(MethodShare::Ratio(0.5)));
+        assert_eq!("Fabrics(2)".parse::<MethodShare>(), Ok(MethodShare::Fabrics(2)));
+        assert!("Fabrics(-2)".parse::<MethodShare>().is_err());
+    }
+    #[test]
+    fn test_forced_import () {
+        let data = Data::load_data_str(WARES1.to_string(), TRANSLATIONS1.to_string()).unwrap();
+        let request = || vec![("Microchips".to_string(), CountsInput::WaresPerMinute(36f64))];
+        let import = |solver| PlanSettings{import: vec!["Silicon Wafers".to_string()], solver, ..Default::default()};
+        for solver in [None, Some(SolverObjective::ModuleCount)] {
+            let calced = data.calc_required_fabric_counts(request(), Vec::new(), Vec::new(), &import(solver)).unwrap();
+            // 5 modules take 20 each
+            let PlannedWare{wares_per_minute, counts, ..} = calced.iter().find(|PlannedWare{ware, ..}| ware == "Silicon Wafers").unwrap();
+            assert!(matches!(counts, CountsOutput::Import));
+            assert!((wares_per_minute - 100.).abs() < 1e-9);
+            assert!(calced.iter().all(|PlannedWare{ware, ..}| ware != "Silicon"));
+        }
+        // a requested ware is bought as well
+        let calced = data.calc_required_fabric_counts(request(), Vec::new(), Vec::new(), &PlanSettings{import: vec!["Microchips".to_string()], ..Default::default()}).unwrap();
+        assert_eq!(calced.len(), 1);
+        assert!(matches!(calced[0].counts, CountsOutput::Import));
+
+        let existing = vec![ExistingModules{ware: "Silicon Wafers".into(), method: "Universal".into(), count: 1}];
+        assert!(matches!(data.calc_required_fabric_counts(request(), Vec::new(), Vec::new(), &PlanSettings{existing_modules: existing, ..import(None)}), Err(DataError::InconsistentRequest(_))));
+    }
+    #[test]
+    fn test_station_balance () {
+        let data = Data::load_data_str(WARES1.to_string(), TRANSLATIONS1.to_string()).unwrap();
+        let modules = vec![("Microchips".to_string(), "Universal".to_string(), 5), ("Silicon Wafers".to_string(), "Universal".to_string(), 1)];
+        let calced = data.calc_station_balance(modules, &PlanSettings::default()).unwrap();
+        let balance = |name: &str| calced.iter().find(|PlannedWare{ware, ..}| ware == name).unwrap().clone();
+
+        assert_eq!(balance("Microchips").balance, WareBalance::new(36., 0., 0.));
+        assert_eq!(balance("Microchips").modules, ModuleCounts{existing: 5, new: 0, total: 5});
+        // 5 modules take 20 wafers each, one wafers module makes 107 in 3 minutes
+        let wafers = balance("Silicon Wafers").balance;
+        assert!((wafers.consumed_per_minute - 100.).abs() < 1e-9);
+        assert!((wafers.surplus_per_minute - (107. / 3. - 100.)).abs() < 1e-9);
+        // 5 by microchips and 30 by wafers
+        let PlannedWare{wares_per_minute, counts, balance: energy, ..} = balance("Energy Cells");
+        assert!(matches!(counts, CountsOutput::Import));
+        assert!((wares_per_minute - 55.).abs() < 1e-9);
+        assert!((energy.surplus_per_minute + 55.).abs() < 1e-9);
+        assert!((balance("Silicon").wares_per_minute - 80.).abs() < 1e-9);
+
+        assert!(matches!(data.calc_station_balance(vec![("Microchips".to_string(), "Teladi".to_string(), 1)], &PlanSettings::default()), Err(DataError::UnknownProductionMethod(_))));
+    }
+    #[test]
+    fn test_economy () {
+        let data = Data::load_data_str(WARES1.to_string(), TRANSLATIONS1.to_string()).unwrap();
+        let request = || vec![("Microchips".to_string(), CountsInput::WaresPerMinute(30f64))];
+        let calc = |price| data.calc_required_fabric_counts(request(), Vec::new(), Vec::new(), &PlanSettings{price, ..Default::default()});
+        let economy = |calced: &[PlannedWare], name: &str| calced.iter().find(|PlannedWare{ware, ..}| ware == name).unwrap().economy.clone().unwrap();
+
+        assert!(calc(None).unwrap().iter().all(|PlannedWare{economy, ..}| economy.is_none()));
+        let calced = calc(Some(PricePoint::Average)).unwrap();
+        // 30 requested and 6 of surplus are sold
+        let microchips = economy(&calced, "Microchips");
+        assert_eq!(microchips.price, 948.);
+        assert!((microchips.revenue_per_hour - 36. * 60. * 948.).abs() < 1e-6);
+        assert_eq!(microchips.cost_per_hour, 0.);
+        // a module makes 7.2 of 948 from 5 energy cells of 16 and 20 wafers of 299
+        assert!((microchips.profit_per_module_per_hour - 60. * (7.2 * 948. - 5. * 16. - 20. * 299.)).abs() < 1e-6);
+        let silicon = economy(&calced, "Silicon");
+        assert!(0. < silicon.cost_per_hour);
+        assert_eq!(silicon.profit_per_hour, -silicon.cost_per_hour);
+        assert_eq!(silicon.profit_per_module_per_hour, 0.);
+
+        assert_eq!(economy(&calc(Some(PricePoint::Fraction(0.5))).unwrap(), "Microchips").price, (805. + 1090.) / 2.);
+        assert_eq!(economy(&calc(Some(PricePoint::Min)).unwrap(), "Microchips").price, 805.);
+        assert!(matches!(calc(Some(PricePoint::Fraction(1.5))), Err(DataError::InconsistentRequest(_))));
+    }
+    #[test]
+    fn test_mining () {
+        let data = Data::load_data_str(WARES1.to_string(), TRANSLATIONS1.to_string()).unwrap();
+        let calced = data.calc_required_fabric_counts(vec![("Microchips".to_string(), CountsInput::WaresPerMinute(30f64))], Vec::new(), Vec::new(), &PlanSettings::default()).unwrap();
+        let imports = || calced.iter().map(|PlannedWare{ware, single_ware, balance, ..}| (ware.as_str(), &single_ware.info, -balance.surplus_per_minute));
+
+        // 3 wafers modules take 80 silicon of volume 10 each
+        let miners = vec![MinerShip{transport: "solid".into(), cargo_volume: 10000., cycle_minutes: 20.}];
+        let (wares, transports) = calc_mining(imports(), &miners);
+        assert_eq!(wares, vec![MiningWare{ware: "Silicon".into(), transport: "solid".into(), wares_per_hour: 3. * 80. * 60., volume_per_hour: 3. * 80. * 60. * 10.}]);
+        // a miner brings 30000 per hour
+        assert_eq!(transports, vec![MiningTransport{transport: "solid".into(), volume_per_hour: 144000., miners: Some(5)}]);
+        assert_eq!(calc_mining(imports(), &[]).1[0].miners, None);
+    }
+    #[test]
+    fn test_build_cost () {
+        // every module is built of 600 energy cells and 60 silicon
+        let module = |id: &str, price: u32| format!(r#"<ware id="{}" transport="container" volume="1" tags="module">
+                                                         <price min="{1}" average="{1}" max="{1}" />
+                                                         <production time="60" amount="1" method="default" name="{{20206,101}}">
+                                                           <primary>
+                                                             <ware ware="energycells" amount="600" />
+                                                             <ware ware="silicon" amount="60" />
+                                                           </primary>
+                                                         </production>
+                                                       </ware>"#, id, price);
+        let wares = WARES1.replace("</wares>", &format!("{}{}{}</wares>", module("module_gen_prod_microchips_01", 1000), module("module_gen_prod_siliconwafers_01", 100), module("module_gen_prod_energycells_01", 100)));
+        let data = Data::load_data_str(wares, TRANSLATIONS1.to_string()).unwrap();
+        let request = || vec![("Microchips".to_string(), CountsInput::WaresPerMinute(30f64))];
+        let new_modules = |calced: &[PlannedWare]| calced.iter().filter_map(|PlannedWare{modules, module, ..}| module.as_ref().map(|module| (module.ware_id.clone(), modules.new))).collect::<Vec<_>>();
+        let energy = |calced: &[PlannedWare]| calced.iter().find(|PlannedWare{ware, ..}| ware == "Energy Cells").map(|PlannedWare{wares_per_minute, counts, ..}| (*wares_per_minute, counts.clone())).unwrap();
+
+        // 5 microchips, 3 wafers and 1 energy modules
+        let calced = data.calc_required_fabric_counts(request(), Vec::new(), Vec::new(), &PlanSettings::default()).unwrap();
+        let (wares, total) = data.calc_build_cost(&new_modules(&calced), PricePoint::Average).unwrap();
+        assert_eq!(wares, vec![BuildWare{ware: "Energy Cells".into(), amount: 5400., price: 16., credits: 86400.}, BuildWare{ware: "Silicon".into(), amount: 540., price: 130., credits: 70200.}]);
+        assert_eq!(total, BuildTotal{wares_credits: 156600., blueprints_credits: 1200., credits: 157800.});
+
+        for solver in [None, Some(SolverObjective::ModuleCount)] {
+            // 10 energy cells per minute for a module built in an hour, the
+            // second energy module is built too
+            let calced = data.calc_required_fabric_counts(request(), Vec::new(), Vec::new(), &PlanSettings{build_hours: Some(1.), solver, ..Default::default()}).unwrap();
+            let (wares_per_minute, counts) = energy(&calced);
+            assert!(matches!(counts, CountsOutput::Produce(_, 2)));
+            assert!((wares_per_minute - (115. + 10. * 10.)).abs() < 1e-9);
+            // existing modules are not built
+            let existing = vec![ExistingModules{ware: "Microchips".into(), method: "Universal".into(), count: 5}];
+            let calced = data.calc_required_fabric_counts(request(), Vec::new(), Vec::new(), &PlanSettings{build_hours: Some(1.), existing_modules: existing, solver, ..Default::default()}).unwrap();
+            let (wares_per_minute, counts) = energy(&calced);
+            assert!(matches!(counts, CountsOutput::Produce(_, 1)));
+            assert!((wares_per_minute - (115. + 4. * 10.)).abs() < 1e-9);
+        }
+
+        let data = Data::load_data_str(WARES1.to_string(), TRANSLATIONS1.to_string()).unwrap();
+        assert!(matches!(data.calc_required_fabric_counts(request(), Vec::new(), Vec::new(), &PlanSettings{build_hours: Some(1.), ..Default::default()}), Err(DataError::ModuleNotFound(..))));
+    }
+    #[test]
+    fn test_production_tree () {
+        let data = Data::load_data_str(WARES1.to_string(), TRANSLATIONS1.to_string()).unwrap();
+        let request = || vec![("Microchips".to_string(), CountsInput::WaresPerMinute(30f64)), ("Energy Cells".to_string(), CountsInput::WaresPerMinute(60f64))];
+        fn flatten (nodes: &[ProductionNode], depth: usize, result: &mut Vec<(usize, String, f64, f64)>) {
+            for node in nodes {
+                result.push((depth, node.ware.clone(), node.wares_per_minute, node.modules));
+                flatten(&node.inputs, depth + 1, result);
+            }
+        }
+        let root = |tree: &[ProductionNode], name: &str| {
+            let mut result = Vec::new();
+            flatten(std::slice::from_ref(tree.iter().find(|node| node.ware == name).unwrap()), 0, &mut result);
+            result
+        };
+        let assert_close = |left: Vec<(usize, String, f64, f64)>, right: Vec<(usize, &str, f64, f64)>| {
+            assert_eq!(left.len(), right.len(), "{:?}", left);
+            for ((depth, ware, wares_per_minute, modules), (expected_depth, expected_ware, expected_wares_per_minute, expected_modules)) in left.iter().zip(right.iter()) {
+                assert_eq!((*depth, ware.as_str()), (*expected_depth, *expected_ware));
+                assert!((wares_per_minute - expected_wares_per_minute).abs() < 1e-9, "{:?}", left);
+                assert!((modules - expected_modules).abs() < 1e-9, "{:?}", left);
+            }
+        };
+
+        let calced = data.calc_required_fabric_counts(request(), Vec::new(), Vec::new(), &PlanSettings::default()).unwrap();
+        let tree = data.calc_production_tree(&calced.iter().collect::<Vec<_>>());
+        assert_eq!(tree.len(), 2);
+        // 30 of the 36 microchips of 5 modules take the inputs of 30 / 7.2
+        // modules, their wafers are a part of the 107 of 3 modules
+        let microchips_modules = 30. / 7.2;
+        let wafers_modules = 3. * 20. * microchips_modules / 107.;
+        let microchips = vec![
+            (0, "Microchips", 30., microchips_modules),
+            (1, "Energy Cells", 5. * microchips_modules, 5. * microchips_modules / 175.),
+            (1, "Silicon Wafers", 20. * microchips_modules, wafers_modules),
+            (2, "Energy Cells", 30. * wafers_modules, 30. * wafers_modules / 175.),
+            (2, "Silicon", 80. * wafers_modules, 0.),
+            ];
+        assert_close(root(&tree, "Microchips"), microchips.clone());
+        assert_close(root(&tree, "Energy Cells"), vec![(0, "Energy Cells", 60., 60. / 175.)]);
+        assert_eq!(tree[0].method.as_deref(), Some("Universal"));
+
+        // spare existing modules feed no one
+        let existing = vec![ExistingModules{ware: "Microchips".into(), method: "Universal".into(), count: 7}];
+        let calced = data.calc_required_fabric_counts(request(), Vec::new(), Vec::new(), &PlanSettings{existing_modules: existing, ..Default::default()}).unwrap();
+        assert!(matches!(calced.iter().find(|PlannedWare{ware, ..}| ware == "Microchips").unwrap().counts, CountsOutput::Produce(_, 7)));
+        let tree = data.calc_production_tree(&calced.iter().collect::<Vec<_>>());
+        assert_close(root(&tree, "Microchips"), microchips);
+    }
+    fn write_catalog (dir: &std::path::Path, name: &str, files: &[(&str, &str)]) {
+        let mut cat = String::new();
+        let mut dat = String::new();
+        for (path, content) in files {
+            cat.push_str(&format!("{} {} 1683547434 00000000000000000000000000000000\n", path, content.len()));
+            dat.push_str(content);
+        }
+        std::fs::write(dir.join(format!("{}.cat", name)), cat).unwrap();
+        std::fs::write(dir.join(format!("{}.dat", name)), dat).unwrap();
+    }
+    fn write_gamedir (name: &str) -> std::path::PathBuf {
+        let gamedir = std::env::temp_dir().join(format!("x4pp-{}-{}", name, std::process::id()));
+        std::fs::create_dir_all(&gamedir).unwrap();
+        let (languages, wares) = WARES1.split_at(WARES1.find("<wares>").unwrap());
+        // wares.xml of 08 is overriden by the later 10
+        write_catalog(&gamedir, "08", &[(LANGUAGES_PATH, languages), (WARES_PATH, "<wares/>")]);
+        write_catalog(&gamedir, "09", &[(&translation_path("44"), TRANSLATIONS1)]);
+        write_catalog(&gamedir, "10", &[(WARES_PATH, wares)]);
+        gamedir
+    }
+    #[test]
+    fn test_gamedir_loading () {
+        let gamedir = write_gamedir("gamedir");
+
+        check_gamedir(&gamedir).unwrap();
+        let data = Data::load_data(&gamedir, &ExtensionsSelection::All).unwrap();
+        let sources = data.gen_sources_list();
+        assert!(sources.contains(&(WARES_PATH.to_string(), gamedir.join("10.cat"))));
+        assert!(sources.contains(&(translation_path("44"), gamedir.join("09.cat"))));
+        let calced = data.calc_required_fabric_counts(vec![("Microchips".to_string(), CountsInput::WaresPerMinute(36f64))], Vec::new(), Vec::new(), &PlanSettings::default()).unwrap();
+        assert_eq!(calced.len(), 4);
+
+        std::fs::remove_dir_all(&gamedir).unwrap();
+    }
+    #[test]
+    fn test_extensions_loading () {
+        let gamedir = write_gamedir("extensions");
+        let extension_dir = gamedir.join(EXTENSIONS_DIR).join("ego_dlc_test");
+        std::fs::create_dir_all(&extension_dir).unwrap();
+        write_catalog(&extension_dir, "ext_01", &[
+            (WARES_PATH, r#"<diff>
+                              <add sel="/wares">
+                                <ware id="spacefuel" name="{20201,4001}" transport="container" volume="4" tags="container economy">
+                                  <price min="50" average="60" max="70" />
+                                  <production time="60" amount="10" method="default" name="{20206,101}">
+                                    <primary>
+                                      <ware ware="energycells" amount="10" />
+                                    </primary>
+                                  </production>
+                                </ware>
+                              </add>
+                              <replace sel="//ware[@id='microchips']/production[@method='default']/@amount">144</replace>
+                            </diff>"#),
+            (&translation_path("44"), r#"<language id="44"><page id="20201"><t id="4001">Spacefuel</t></page></language>"#),
+            ]);
+
+        let data = Data::load_data(&gamedir, &ExtensionsSelection::All).unwrap();
+        let calced = data.calc_required_fabric_counts(vec![("Spacefuel".to_string(), CountsInput::Fabrics(1))], Vec::new(), Vec::new(), &PlanSettings::default()).unwrap();
+        assert_eq!(calced.len(), 2);
+        assert_eq!(data.wares.get("microchips").unwrap().production_methods[0].1.wares_per_cicle, 144);
+
+        let data = Data::load_data(&gamedir, &ExtensionsSelection::AllExcept(vec!["ego_dlc_test".into()])).unwrap();
+        assert!(data.calc_required_fabric_counts(vec![("Spacefuel".to_string(), CountsInput::Fabrics(1))], Vec::new(), Vec::new(), &PlanSettings::default()).is_err());
+
+        std::fs::remove_dir_all(&gamedir).unwrap();
+    }
+    #[test]
+    fn test_production_modules () {
+        let gamedir = write_gamedir("modules");
+        // a teladi method and its module named against the convention, made
+        // by an extension macro
+        let wares = wares_with_teladi_microchips();
+        let wares = wares[wares.find("<wares>").unwrap()..]
+                        .replace("</wares>", r#"<ware id="module_gen_prod_microchips_01" transport="container" volume="1" tags="module">
+                                                  <price min="1000" average="1000" max="1000" />
+                                                  <component ref="prod_gen_microchips_macro" />
+                                                </ware>
+                                                <ware id="module_tel_prod_chips_02" transport="container" volume="1" tags="module">
+                                                  <price min="9000" average="9000" max="9000" />
+                                                  <component ref="prod_tel_chips_macro" />
+                                                </ware>
+                                                <ware id="module_gen_prod_energycells_01" transport="container" volume="1" tags="module">
+                                                  <price min="100" average="100" max="100" />
+                                                </ware>
+                                              </wares>"#);
+        write_catalog(&gamedir, "11", &[
+            (WARES_PATH, &wares),
+            (MACROS_INDEX_PATH, r#"<index><entry name="prod_gen_microchips_macro" value="assets\structures\production\macros\prod_gen_microchips_macro" /></index>"#),
+            ("assets/structures/production/macros/prod_gen_microchips_macro.xml", r#"<macros>
+                                                                                      <macro name="prod_gen_microchips_macro" class="production">
+                                                                                        <properties>
+                                                                                          <identification makerrace="argon" />
+                                                                                          <production wares="microchips" />
+                                                                                          <workforce max="90" />
+                                                                                        </properties>
+                                                                                      </macro>
+                                                                                    </macros>"#),
+            ]);
+        let extension_dir = gamedir.join(EXTENSIONS_DIR).join("ego_dlc_test");
+        std::fs::create_dir_all(&extension_dir).unwrap();
+        write_catalog(&extension_dir, "ext_01", &[
+            (MACROS_INDEX_PATH, r#"<diff><add sel="/index"><entry name="prod_tel_chips_macro" value="extensions\ego_dlc_test\assets\structures\production\macros\prod_tel_chips_macro" /></add></diff>"#),
+            ("assets/structures/production/macros/prod_tel_chips_macro.xml", r#"<macros>
+                                                                                 <macro name="prod_tel_chips_macro" class="production">
+                                                                                   <properties>
+                                                                                     <identification makerrace="teladi" />
+                                                                                     <production wares="microchips">
+                                                                                       <queue ware="microchips" method="teladi" />
+                                                                                     </production>
+                                                                                   </properties>
+                                                                                 </macro>
+                                                                               </macros>"#),
+            ]);
+
+        let data = Data::load_data(&gamedir, &ExtensionsSelection::All).unwrap();
+        assert_eq!(data.get_production_modules().len(), 2);
+        assert_eq!(data.get_production_modules()[0].workforce, Some(90));
+        let request = || vec![("Microchips".to_string(), CountsInput::WaresPerMinute(36f64))];
+        let module = |calced: &[PlannedWare], name: &str| calced.iter().find(|PlannedWare{ware, ..}| ware == name).unwrap().module.clone();
+        let calced = data.calc_required_fabric_counts(request(), vec!["Teladi".to_string()], Vec::new(), &PlanSettings::default()).unwrap();
+        assert_eq!(module(&calced, "Microchips"), Some(PlannedModule{ware_id: "module_tel_prod_chips_02".into(), module: "module_tel_prod_chips_02".into(), race: Some("teladi".into()), blueprint_price: 9000}));
+        // by the naming convention, without a macro
+        assert_eq!(module(&calced, "Energy Cells"), Some(PlannedModule{ware_id: "module_gen_prod_energycells_01".into(), module: "module_gen_prod_energycells_01".into(), race: None, blueprint_price: 100}));
+        assert_eq!(module(&calced, "Silicon Wafers"), None);
+        assert_eq!(module(&calced, "Silicon"), None);
+        let calced = data.calc_required_fabric_counts(request(), vec!["Universal".to_string()], Vec::new(), &PlanSettings::default()).unwrap();
+        assert_eq!(module(&calced, "Microchips").unwrap().race, Some("argon".into()));
+
+        // the macro of a disabled extension is unknown
+        let data = Data::load_data(&gamedir, &ExtensionsSelection::AllExcept(vec!["ego_dlc_test".into()])).unwrap();
+        assert_eq!(data.get_production_modules().len(), 1);
+
+        std::fs::remove_dir_all(&gamedir).unwrap();
+    }
+    #[test]
+    fn test_storage () {
+        let gamedir = write_gamedir("storage");
+        let storage = |id: &str, macro_name: &str| format!(r#"<ware id="{}" transport="container" volume="1" tags="module">
+                                                              <price min="100" average="100" max="100" />
+                                                              <component ref="{}" />
+                                                            </ware>"#, id, macro_name);
+        let storage_macro = |macro_name: &str, cargo: &str| format!(r#"<macros>
+                                                                         <macro name="{}" class="storage">
+                                                                           <properties>
+                                                                             <identification makerrace="argon" />
+                                                                             {}
+                                                                           </properties>
+                                                                         </macro>
+                                                                       </macros>"#, macro_name, cargo);
+        let wares = WARES1[WARES1.find("<wares>").unwrap()..]
+                        .replace("</wares>", &format!("{}{}{}</wares>",
+                                                      storage("module_arg_stor_container_m_01", "storage_arg_m_container_01_macro"),
+                                                      storage("module_arg_stor_container_l_01", "storage_arg_l_container_01_macro"),
+                                                      storage("module_arg_stor_solid_l_01", "storage_arg_l_solid_01_macro")));
+        let storage_dir = "assets/structures/storage/macros";
+        write_catalog(&gamedir, "11", &[
+            (WARES_PATH, &wares),
+            (MACROS_INDEX_PATH, r#"<index>
+                                     <entry name="storage_arg_m_container_01_macro" value="assets\structures\storage\macros\storage_arg_m_container_01_macro" />
+                                     <entry name="storage_arg_l_container_01_macro" value="assets\structures\storage\macros\storage_arg_l_container_01_macro" />
+                                   </index>"#),
+            (&format!("{}/storage_arg_m_container_01_macro.xml", storage_dir), &storage_macro("storage_arg_m_container_01_macro", r#"<cargo max="200000" tags="container" />"#)),
+            (&format!("{}/storage_arg_l_container_01_macro.xml", storage_dir), &storage_macro("storage_arg_l_container_01_macro", r#"<cargo max="500000" tags="container" />"#)),
+            // not in the index, found by the directory
+            (&format!("{}/storage_arg_l_solid_01_macro.xml", storage_dir), &storage_macro("storage_arg_l_solid_01_macro", r#"<cargo max="100000" tags="solid" />"#)),
+            ]);
+
+        let data = Data::load_data(&gamedir, &ExtensionsSelection::All).unwrap();
+        assert_eq!(data.get_storage_modules().len(), 3);
+        let request = || vec![("Microchips".to_string(), CountsInput::WaresPerMinute(30f64))];
+        let calced = data.calc_required_fabric_counts(request(), Vec::new(), Vec::new(), &PlanSettings::default()).unwrap();
+        assert!(data.calc_storage(calced.iter()).is_empty());
+
+        // 2 hours of 36 microchips (30 requested), 100 of 107 wafers, 115 of
+        // 175 energy cells and 240 imported silicon
+        let calced = data.calc_required_fabric_counts(request(), Vec::new(), Vec::new(), &PlanSettings{storage_hours: Some(2.), ..Default::default()}).unwrap();
+        let storage = |calced: &[PlannedWare], name: &str| calced.iter().find(|PlannedWare{ware, ..}| ware == name).unwrap().storage.clone().unwrap();
+        assert_eq!(storage(&calced, "Microchips"), WareStorage{input_volume: 0., intermediate_volume: 0., output_volume: 36. * 120. * 22.});
+        assert_eq!(storage(&calced, "Silicon"), WareStorage{input_volume: 240. * 120. * 10., intermediate_volume: 0., output_volume: 0.});
+        let transports = data.calc_storage(calced.iter());
+        assert_eq!(transports.iter().map(|transport| (transport.transport.as_str(), transport.module.as_deref(), transport.modules)).collect::<Vec<_>>(),
+                   vec![("container", Some("module_arg_stor_container_l_01"), Some(1)), ("solid", Some("module_arg_stor_solid_l_01"), Some(3))]);
+        assert_eq!(transports[1].input_volume, 288000.);
+        assert_eq!(transports[0].volume, transports[0].intermediate_volume + transports[0].output_volume);
+
+        // without storage modules only volumes are planned
+        let data = Data::load_data_str(WARES1.to_string(), TRANSLATIONS1.to_string()).unwrap();
+        let calced = data.calc_required_fabric_counts(request(), Vec::new(), Vec::new(), &PlanSettings{storage_hours: Some(2.), ..Default::default()}).unwrap();
+        assert!(data.calc_storage(calced.iter()).iter().all(|transport| transport.module.is_none() && transport.modules.is_none()));
+
+        std::fs::remove_dir_all(&gamedir).unwrap();
+    }
+    #[test]
+    fn test_workforce () {
+        let gamedir = write_gamedir("workforce");
+        // every production module has 400 jobs, 500 workers eat 500 energy
+        // cells in 10 minutes, 0.1 each per minute
+        let production = |ware_id: &str| format!(r#"<ware id="module_gen_prod_{0}_01" transport="container" volume="1" tags="module">
+                                                      <price min="100" average="100" max="100" />
+                                                      <component ref="prod_gen_{0}_macro" />
+                                                    </ware>"#, ware_id);
+        let production_macro = |ware_id: &str| format!(r#"<macros>
+                                                            <macro name="prod_gen_{0}_macro" class="production">
+                                                              <properties>
+                                                                <production wares="{0}" />
+                                                                <workforce max="400" />
+                                                              </properties>
+                                                            </macro>
+                                                          </macros>"#, ware_id);
+        // a habitat is built of 600 energy cells
+        let habitat = |id: &str, name_id: u32| format!(r#"<ware id="module_arg_hab_{0}_01" name="{{20201,{1}}}" transport="container" volume="1" tags="module">
+                                                         <price min="100" average="100" max="100" />
+                                                         <component ref="hab_arg_{0}_01_macro" />
+                                                         <production time="60" amount="1" method="default" name="{{20206,101}}">
+                                                           <primary>
+                                                             <ware ware="energycells" amount="600" />
+                                                           </primary>
+                                                         </production>
+                                                       </ware>"#, id, name_id);
+        let habitat_macro = |id: &str, capacity: u32| format!(r#"<macros>
+                                                                   <macro name="hab_arg_{0}_01_macro" class="habitation">
+                                                                     <properties>
+                                                                       <identification makerrace="argon" />
+                                                                       <workforce capacity="{1}" race="argon" />
+                                                                     </properties>
+                                                                   </macro>
+                                                                 </macros>"#, id, capacity);
+        let wares = WARES1[WARES1.find("<wares>").unwrap()..]
+                        .replace("</wares>", &format!(r#"<ware id="workunit_busy" transport="workunit" volume="1" tags="noplayerbuild workunit">
+                                                           <production time="600" amount="500" method="default" name="{{20206,101}}">
+                                                             <primary>
+                                                               <ware ware="energycells" amount="500" />
+                                                             </primary>
+                                                           </production>
+                                                         </ware>
+                                                         {}{}{}{}{}{}
+                                                       </wares>"#,
+                                                      production("microchips"), production("siliconwafers"), production("energycells"), habitat("m", 9001), habitat("s", 9002), habitat("l", 9003)));
+        let translations = TRANSLATIONS1.replace(r#"<t id="3604">Silicon Refinery</t>"#, r#"<t id="3604">Silicon Refinery</t><t id="9001">Argon M Habitat</t><t id="9002">Argon S Habitat</t><t id="9003">Argon L Habitat</t>"#);
+        let production_dir = "assets/structures/production/macros";
+        let habitat_dir = "assets/structures/habitat/macros";
+        write_catalog(&gamedir, "11", &[
+            (WARES_PATH, &wares),
+            (&translation_path("44"), &translations),
+            (&format!("{}/prod_gen_microchips_macro.xml", production_dir), &production_macro("microchips")),
+            (&format!("{}/prod_gen_siliconwafers_macro.xml", production_dir), &production_macro("siliconwafers")),
+            (&format!("{}/prod_gen_energycells_macro.xml", production_dir), &production_macro("energycells")),
+            (&format!("{}/hab_arg_m_01_macro.xml", habitat_dir), &habitat_macro("m", 1000)),
+            (&format!("{}/hab_arg_s_01_macro.xml", habitat_dir), &habitat_macro("s", 250)),
+            (&format!("{}/hab_arg_l_01_macro.xml", habitat_dir), &habitat_macro("l", 0)),
+            ]);
+
+        let data = Data::load_data(&gamedir, &ExtensionsSelection::All).unwrap();
+        assert_eq!(data.get_habitat_modules().len(), 3);
+        let request = || vec![("Microchips".to_string(), CountsInput::WaresPerMinute(30f64))];
+        let settings = |workforce: Workforce, build_hours, solver| PlanSettings{workforce_percent: 100., workforce: Some(workforce), build_hours, solver, ..Default::default()};
+        let modules = |calced: &[PlannedWare]| calced.iter().map(|PlannedWare{modules, ..}| modules.total).sum::<usize>();
+        let energy = |calced: &[PlannedWare]| calced.iter().find(|PlannedWare{ware, ..}| ware == "Energy Cells").map(|PlannedWare{wares_per_minute, counts, ..}| (*wares_per_minute, counts.clone())).unwrap();
+
+        for solver in [None, Some(SolverObjective::ModuleCount)] {
+            // staffed modules make more: 4 microchips and 2 wafers modules take
+            // 80 and the 3200 workers of all 8 modules 320, over one energy
+            // module of 250.25
+            let calced = data.calc_required_fabric_counts(request(), Vec::new(), Vec::new(), &settings(Workforce::default(), None, solver)).unwrap();
+            let (wares_per_minute, counts) = energy(&calced);
+            assert!(matches!(counts, CountsOutput::Produce(_, 2)));
+            assert!((wares_per_minute - (80. + 8. * 40.)).abs() < 1e-9);
+            assert_eq!(modules(&calced), 8);
+            // the habitat housing the most workers
+            assert_eq!(data.calc_habitats(calced.iter(), &settings(Workforce::default(), None, solver)).unwrap(),
+                       Some(Habitats{workers: 3200., ware_id: "module_arg_hab_m_01".into(), habitat: "Argon M Habitat".into(), capacity: 1000, habitats: 4}));
+            let workforce = Workforce{method: None, habitat: Some("Argon S Habitat".into())};
+            assert_eq!(data.calc_habitats(calced.iter(), &settings(workforce, None, solver)).unwrap().unwrap().habitats, 13);
+
+            // building the 4 habitats in an hour takes 40 energy cells per
+            // minute more
+            let calced = data.calc_required_fabric_counts(request(), Vec::new(), Vec::new(), &settings(Workforce::default(), Some(1.), solver)).unwrap();
+            let (wares_per_minute, counts) = energy(&calced);
+            assert!(matches!(counts, CountsOutput::Produce(_, 2)));
+            assert!((wares_per_minute - (80. + 8. * 40. + 40.)).abs() < 1e-9);
+        }
+        // workers of the station eat too
+        let calced = data.calc_station_balance(vec![("Microchips".to_string(), "Universal".to_string(), 5)], &settings(Workforce::default(), None, None)).unwrap();
+        let PlannedWare{wares_per_minute, ..} = calced.iter().find(|PlannedWare{ware, ..}| ware == "Energy Cells").unwrap();
+        assert!((wares_per_minute - 5. * (5. + 40.)).abs() < 1e-9);
+
+        assert_eq!(data.calc_habitats(calced.iter(), &PlanSettings::default()).unwrap(), None);
+        let workforce = Workforce{method: None, habitat: Some("Microchips".into())};
+        assert!(matches!(data.calc_required_fabric_counts(request(), Vec::new(), Vec::new(), &settings(workforce, None, None)), Err(DataError::HabitatNotFound(_))));
+        // a habitat housing no one can't take the workers
+        let workforce = Workforce{method: None, habitat: Some("Argon L Habitat".into())};
+        assert!(matches!(data.calc_required_fabric_counts(request(), Vec::new(), Vec::new(), &settings(workforce.clone(), None, None)), Err(DataError::HabitatNotFound(_))));
+        assert!(matches!(data.calc_habitats(calced.iter(), &settings(workforce, None, None)), Err(DataError::HabitatNotFound(_))));
+        // the default workforce_percent of 0 would staff no one
+        let unstaffed = PlanSettings{workforce: Some(Workforce::default()), ..Default::default()};
+        assert!(matches!(data.calc_required_fabric_counts(request(), Vec::new(), Vec::new(), &unstaffed), Err(DataError::InconsistentRequest(_))));
+        assert!(matches!(data.calc_station_balance(vec![("Microchips".to_string(), "Universal".to_string(), 5)], &unstaffed), Err(DataError::InconsistentRequest(_))));
+        // without macros modules have no jobs, the workers still need their ware
+        let data = Data::load_data_str(WARES1.to_string(), TRANSLATIONS1.to_string()).unwrap();
+        assert!(matches!(data.calc_required_fabric_counts(request(), Vec::new(), Vec::new(), &settings(Workforce::default(), None, None)), Err(DataError::UnknownWare(_))));
+
+        std::fs::remove_dir_all(&gamedir).unwrap();
+    }
+    #[test]
+    fn test_data_cache () {
+        let gamedir = write_gamedir("cache");
+        let cache_dir = gamedir.join("cache");
+        let request = || vec![("Microchips".to_string(), CountsInput::WaresPerMinute(36f64))];
+
+        let data = Data::load_data_cached(&gamedir, &ExtensionsSelection::All, false, &cache_dir).unwrap();
+        assert_eq!(data.calc_required_fabric_counts(request(), Vec::new(), Vec::new(), &PlanSettings::default()).unwrap().len(), 4);
+        let cache_path = std::fs::read_dir(&cache_dir).unwrap().next().unwrap().unwrap().path();
+
+        // the next load must take the data from the cache, not from catalogs
+        let cached = std::fs::read_to_string(&cache_path).unwrap();
+        std::fs::write(&cache_path, cached.replace("\"Microchips\"", "\"Cached Microchips\"")).unwrap();
+        let data = Data::load_data_cached(&gamedir, &ExtensionsSelection::All, false, &cache_dir).unwrap();
+        assert!(data.calc_required_fabric_counts(vec![("Cached Microchips".to_string(), CountsInput::WaresPerMinute(36f64))], Vec::new(), Vec::new(), &PlanSettings::default()).is_ok());
+
+        // but not a cache of an older format
+        let cached = std::fs::read_to_string(&cache_path).unwrap();
+        assert!(cached.contains(&format!("format = {}", cache::CACHE_FORMAT_VERSION)));
+        std::fs::write(&cache_path, cached.replace(&format!("format = {}", cache::CACHE_FORMAT_VERSION), "format = 0")).unwrap();
+        let data = Data::load_data_cached(&gamedir, &ExtensionsSelection::All, false, &cache_dir).unwrap();
+        assert!(data.calc_required_fabric_counts(vec![("Cached Microchips".to_string(), CountsInput::WaresPerMinute(36f64))], Vec::new(), Vec::new(), &PlanSettings::default()).is_err());
+
+        // and to reparse once a catalog changes
+        write_catalog(&gamedir, "11", &[("readme.txt", "changed")]);
+        let data = Data::load_data_cached(&gamedir, &ExtensionsSelection::All, false, &cache_dir).unwrap();
+        assert!(data.calc_required_fabric_counts(request(), Vec::new(), Vec::new(), &PlanSettings::default()).is_ok());
+
+        std::fs::remove_dir_all(&gamedir).unwrap();
+    }
+    #[test]
+    fn test_unpacked_loading () {
+        let gamedir = std::env::temp_dir().join(format!("x4pp-unpacked-{}", std::process::id()));
+        std::fs::create_dir_all(gamedir.join("libraries")).unwrap();
+        std::fs::create_dir_all(gamedir.join("t")).unwrap();
+        let (languages, wares) = WARES1.split_at(WARES1.find("<wares>").unwrap());
+        std::fs::write(gamedir.join(LANGUAGES_PATH), languages).unwrap();
+        std::fs::write(gamedir.join(WARES_PATH), wares).unwrap();
+        std::fs::write(gamedir.join(translation_path("44")), TRANSLATIONS1).unwrap();
+
+        check_unpacked_gamedir(&gamedir).unwrap();
+        assert!(check_gamedir(&gamedir).is_err());
+        let data = Data::load_data_unpacked(&gamedir, &ExtensionsSelection::All).unwrap();
+        assert!(data.gen_sources_list().contains(&(WARES_PATH.to_string(), gamedir.join(WARES_PATH))));
+        let calced = data.calc_required_fabric_counts(vec![("Microchips".to_string(), CountsInput::WaresPerMinute(36f64))], Vec::new(), Vec::new(), &PlanSettings::default()).unwrap();
+        assert_eq!(calced.len(), 4);
+
+        std::fs::remove_dir_all(&gamedir).unwrap();
+    }
+    #[test]
     fn test_translations_parsing () {
         let content = WARES2.to_string();
         let translations = TRANSLATIONS2.to_string();
@@ -1409,8 +4097,8 @@ const TRANSLATIONS2: &'static str = r#"
         println!("## Done Wares = {:?}", wares);
         let translations = Translations::load_from_string(translations, &wares, langs, "local".into()).unwrap();
         println!("## Done Translations = {:?}", translations);
-        let data = Data{wares, translations};
-        let calced = data.calc_required_fabric_counts(vec![("ARG S All-round Engine Mk1".to_string(), CountsInput::WaresPerMinute(36f64))], Vec::new(), Vec::new()).unwrap();
+        let data = Data{wares, translations, sources: Vec::new()};
+        let calced = data.calc_required_fabric_counts(vec![("ARG S All-round Engine Mk1".to_string(), CountsInput::WaresPerMinute(36f64))], Vec::new(), Vec::new(), &PlanSettings::default()).unwrap();
         println!("## Calced = {:?}", calced);
     }
 }
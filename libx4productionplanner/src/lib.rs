@@ -1,13 +1,18 @@
+mod cache;
+mod catalog;
 mod config;
 //mod i18n;
 mod logic;
 mod dataloader;
+mod xmldiff;
 
 use config::*;
 //use i18n::*;
 use dataloader::*;
 
-pub use dataloader::{CountsInput, CountsOutput};
+pub use cache::default_cache_dir;
+pub use dataloader::{BuildTotal, BuildWare, Habitats, MinerShip, MiningTransport, MiningWare, ProductionNode, TransportStorage};
+pub use dataloader::{CountsInput, CountsOutput, ExistingModules, ExtensionsSelection, ExternalSupply, MethodShare, MethodSplit, PlanSettings, PricePoint, SolverObjective, WareMethods, Workforce};
 
 #[derive(Debug)]
 pub enum Error {
@@ -25,37 +30,210 @@ impl From<ConfigError> for Error {
     }
 }
 
+// Several methods in one csv cell, comma separated
+fn my_list_serializer<S: serde::Serializer>(value: &[String], serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.serialize_str(&value.join(","))
+}
+fn my_list_deserializer<'de, D: serde::Deserializer<'de>>(deserializer: D) -> Result<Vec<String>, D::Error> {
+    let value = <String as serde::Deserialize>::deserialize(deserializer)?;
+    Ok(value.split(',').map(str::trim).filter(|item| !item.is_empty()).map(String::from).collect())
+}
+// "Universal:Ratio(0.6),Teladi:Ratio(0.4)"
+fn my_split_serializer<S: serde::Serializer>(value: &[MethodSplit], serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.serialize_str(&value.iter().map(|split| format!("{}:{}", split.method, split.share)).collect::<Vec<_>>().join(","))
+}
+fn my_split_deserializer<'de, D: serde::Deserializer<'de>>(deserializer: D) -> Result<Vec<MethodSplit>, D::Error> {
+    use serde::de::Error;
+
+    my_list_deserializer(deserializer)?.into_iter()
+        .map(|item| {
+            let (method, share) = item.rsplit_once(':').ok_or_else(|| D::Error::custom(format!("Expected method:share, got \"{}\"", item)))?;
+            Ok(MethodSplit{method: method.trim().to_string(), share: share.trim().parse().map_err(D::Error::custom)?})
+        })
+        .collect()
+}
+
+// A row with zero production only sets methods of a ware, e.g. of an
+// intermediate one
 #[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
 pub struct WareRequest {
     pub name:            String,
     pub production_kind: CountsInput,
+    // `WareMethods` of this ware, the columns may be omitted
+    #[serde(default)]
+    pub method:          Option<String>,
+    #[serde(default, serialize_with = "my_list_serializer", deserialize_with = "my_list_deserializer")]
+    pub blacklist:       Vec<String>,
+    #[serde(default, serialize_with = "my_split_serializer", deserialize_with = "my_split_deserializer")]
+    pub split:           Vec<MethodSplit>,
 }
 impl WareRequest {
+    fn ware_methods (&self) -> Option<WareMethods> {
+        if self.method.is_none() && self.blacklist.is_empty() && self.split.is_empty() {
+            None
+        }
+        else {
+            Some(WareMethods{ware: self.name.clone(), method: self.method.clone(), blacklist: self.blacklist.clone(), split: self.split.clone()})
+        }
+    }
     fn into_tuple (self) -> (String, CountsInput) {
         (self.name, self.production_kind)
     }
 }
 
+// Modules a station is built of, for `X4ProductionPlanner::calc_station_balance`
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub struct ModuleRequest {
+    pub ware:   String,
+    pub method: String,
+    pub count:  usize,
+}
+
 fn my_count_serializer<S: serde::Serializer>(value: &CountsOutput, serializer: S) -> Result<S::Ok, S::Error> {
     use serde::Serialize;
     value.serialize(serializer)
 }
 
+// Optional ware metadata columns of `WareResponse`
+#[derive(Debug, Clone, Copy, PartialEq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum WareColumn {
+    Group,
+    Volume,
+    PriceMin,
+    PriceAverage,
+    PriceMax,
+    Transport,
+    Tags,
+    Description,
+    Factoryname,
+}
+
 #[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
 pub struct WareResponse {
     pub name:             String,
     pub wares_per_minute: f64,
     #[serde(serialize_with = "my_count_serializer")]
     pub counts_output:    CountsOutput,
+    // `WareBalance`, csv can't flatten
+    pub produced_per_minute: f64,
+    pub consumed_per_minute: f64,
+    pub requested_per_minute: f64,
+    pub surplus_per_minute:  f64,
+    pub utilization_percent: f64,
+    // `ModuleCounts`
+    pub existing_modules:    usize,
+    pub new_modules:         usize,
+    pub total_modules:       usize,
+    // `WareEconomy`, if `PlanSettings::price` is set
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub price:                      Option<f64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub revenue_per_hour:           Option<f64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cost_per_hour:              Option<f64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub profit_per_hour:            Option<f64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub profit_per_module_per_hour: Option<f64>,
+    // `PlannedModule`, empty for imports. Not skipped, rows differ in it.
+    #[serde(default)]
+    pub module:                     Option<String>,
+    #[serde(default)]
+    pub module_race:                Option<String>,
+    #[serde(default)]
+    pub blueprint_price:            Option<u32>,
+    // `WareStorage`, if `PlanSettings::storage_hours` is set
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub storage_input_volume:        Option<f64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub storage_intermediate_volume: Option<f64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub storage_output_volume:       Option<f64>,
+
+    // filled by `X4ProductionPlanner::fill_columns`, all rows get the same
+    // set so that csv columns stay aligned
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub group:            Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub volume:           Option<u32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub price_min:        Option<u32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub price_average:    Option<u32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub price_max:        Option<u32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub transport:        Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tags:             Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub description:      Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub factoryname:      Option<String>,
+}
+// Station totals of the economic report
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct EconomyTotal {
+    pub revenue_per_hour: f64,
+    pub cost_per_hour:    f64,
+    pub profit_per_hour:  f64,
+}
+impl EconomyTotal {
+    // none without a price point
+    pub fn from_responses (responses: &[WareResponse]) -> Option<Self> {
+        responses.iter().try_fold(
+            Self{revenue_per_hour: 0., cost_per_hour: 0., profit_per_hour: 0.},
+            |acc, response| Some(Self {
+                revenue_per_hour: acc.revenue_per_hour + response.revenue_per_hour?,
+                cost_per_hour:    acc.cost_per_hour + response.cost_per_hour?,
+                profit_per_hour:  acc.profit_per_hour + response.profit_per_hour?,
+            }))
+            .filter(|_| !responses.is_empty())
+    }
 }
+
 pub struct WareResponseExt {
     pub response: WareResponse,
-    pub ext:      SingleWare,
+    pub ext:      PlannedWare,
 }
 impl WareResponseExt {
-    fn from_tuple ((name, wares_per_minute, counts_output, ext): (String, f64, CountsOutput, SingleWare)) -> Self {
+    fn from_planned (ext: PlannedWare) -> Self {
+        let PlannedWare{balance, modules, economy, module, storage, ..} = &ext;
         Self {
-            response: WareResponse {name, wares_per_minute, counts_output},
+            response: WareResponse {
+                          name:                ext.ware.clone(),
+                          wares_per_minute:    ext.wares_per_minute,
+                          counts_output:       ext.counts.clone(),
+                          produced_per_minute: balance.produced_per_minute,
+                          consumed_per_minute: balance.consumed_per_minute,
+                          requested_per_minute: balance.requested_per_minute,
+                          surplus_per_minute:  balance.surplus_per_minute,
+                          utilization_percent: balance.utilization_percent,
+                          existing_modules:    modules.existing,
+                          new_modules:         modules.new,
+                          total_modules:       modules.total,
+                          price:                      economy.as_ref().map(|economy| economy.price),
+                          revenue_per_hour:           economy.as_ref().map(|economy| economy.revenue_per_hour),
+                          cost_per_hour:              economy.as_ref().map(|economy| economy.cost_per_hour),
+                          profit_per_hour:            economy.as_ref().map(|economy| economy.profit_per_hour),
+                          profit_per_module_per_hour: economy.as_ref().map(|economy| economy.profit_per_module_per_hour),
+                          module:                     module.as_ref().map(|module| module.module.clone()),
+                          module_race:                module.as_ref().and_then(|module| module.race.clone()),
+                          blueprint_price:            module.as_ref().map(|module| module.blueprint_price),
+                          storage_input_volume:        storage.as_ref().map(|storage| storage.input_volume),
+                          storage_intermediate_volume: storage.as_ref().map(|storage| storage.intermediate_volume),
+                          storage_output_volume:       storage.as_ref().map(|storage| storage.output_volume),
+                          group:         None,
+                          volume:        None,
+                          price_min:     None,
+                          price_average: None,
+                          price_max:     None,
+                          transport:     None,
+                          tags:          None,
+                          description:   None,
+                          factoryname:   None,
+                      },
             ext,
         }
     }
@@ -70,25 +248,112 @@ pub struct X4ProductionPlanner {
 impl X4ProductionPlanner {
     //fn new (config: Config) -> Result<(), ()> {
     //}
-    pub fn new (gamedir: &std::path::Path) -> Result<Self, Error> {
+    // Both `new` and `new_from_unpacked` parse the game files every time, see
+    // `new_with_cache` to reuse parsed data
+    pub fn new (gamedir: &std::path::Path, extensions: &ExtensionsSelection) -> Result<Self, Error> {
+        Self::new_with_cache(gamedir, false, extensions, None)
+    }
+    // Parsed data is cached in `cache_dir_opt`, e.g. `default_cache_dir()`,
+    // nothing is read or written if it is none
+    pub fn new_with_cache (gamedir: &std::path::Path, is_unpacked: bool, extensions: &ExtensionsSelection, cache_dir_opt: Option<&std::path::Path>) -> Result<Self, Error> {
+        let data
+            = match (cache_dir_opt, is_unpacked) {
+                  (Some(cache_dir), _) => Data::load_data_cached(gamedir, extensions, is_unpacked, cache_dir)?,
+                  (None, false) => Data::load_data(gamedir, extensions)?,
+                  (None, true) => Data::load_data_unpacked(gamedir, extensions)?,
+              };
         Ok(Self {
             config: Config::new(),
-            data: Data::load_data(gamedir)?,
+            data,
         })
     }
+    pub fn check_gamedir (gamedir: &std::path::Path, is_unpacked: bool) -> Result<(), Error> {
+        if is_unpacked {
+            check_unpacked_gamedir(gamedir)?;
+        }
+        else {
+            check_gamedir(gamedir)?;
+        }
+        Ok(())
+    }
+    // From the game files extracted by X Catalog Tool
+    pub fn new_from_unpacked (dir: &std::path::Path, extensions: &ExtensionsSelection) -> Result<Self, Error> {
+        Self::new_with_cache(dir, true, extensions, None)
+    }
     pub fn new_from_data_str (wares_xml_str: String, translation_xml_str: String) -> Result<Self, Error> {
         Ok(Self {
             config: Config::new(),
             data: Data::load_data_str(wares_xml_str, translation_xml_str)?,
         })
     }
-    pub fn calc_required_fabric_counts (&mut self, desired_unicode_id_opt: Option<String>, desired_outputs: Vec<WareRequest>, prioritylist: Vec<String>, blacklist: Vec<String>) -> Result<Vec<WareResponseExt>, Error> {
+    pub fn calc_required_fabric_counts (&mut self, desired_unicode_id_opt: Option<String>, desired_outputs: Vec<WareRequest>, prioritylist: Vec<String>, blacklist: Vec<String>, settings: &PlanSettings) -> Result<Vec<WareResponseExt>, Error> {
         if let Some(desired_unicode_id) = desired_unicode_id_opt {
             self.data.set_desired_unicode_id(desired_unicode_id);
         }
+        let mut settings = settings.clone();
+        settings.ware_methods.extend(desired_outputs.iter().filter_map(WareRequest::ware_methods));
         let input = desired_outputs.into_iter().map(|v| v.into_tuple()).collect();
-        let result = self.data.calc_required_fabric_counts(input, prioritylist, blacklist)?;
-        Ok(result.into_iter().map(|v| WareResponseExt::from_tuple(v)).collect())
+        let result = self.data.calc_required_fabric_counts(input, prioritylist, blacklist, &settings)?;
+        Ok(result.into_iter().map(WareResponseExt::from_planned).collect())
+    }
+    pub fn calc_station_balance (&mut self, desired_unicode_id_opt: Option<String>, modules: Vec<ModuleRequest>, settings: &PlanSettings) -> Result<Vec<WareResponseExt>, Error> {
+        if let Some(desired_unicode_id) = desired_unicode_id_opt {
+            self.data.set_desired_unicode_id(desired_unicode_id);
+        }
+        let input = modules.into_iter().map(|m| (m.ware, m.method, m.count)).collect();
+        let result = self.data.calc_station_balance(input, settings)?;
+        Ok(result.into_iter().map(WareResponseExt::from_planned).collect())
+    }
+    // Imported minables of a plan, a negative surplus is the amount to mine
+    pub fn calc_mining (&self, responses: &[WareResponseExt], settings: &PlanSettings) -> (Vec<MiningWare>, Vec<MiningTransport>) {
+        let imports
+            = responses.iter()
+              .filter(|r_ext| matches!(r_ext.response.counts_output, CountsOutput::Import))
+              .map(|r_ext| (r_ext.response.name.as_str(), &r_ext.ext.single_ware.info, -r_ext.response.surplus_per_minute));
+        dataloader::calc_mining(imports, &settings.miners)
+    }
+    // Workers of all the modules of a plan, none without a workforce setting
+    pub fn calc_habitats (&self, responses: &[WareResponseExt], settings: &PlanSettings) -> Result<Option<Habitats>, Error> {
+        Ok(self.data.calc_habitats(responses.iter().map(|r_ext| &r_ext.ext), settings)?)
+    }
+    // Construction wares and blueprints of the new modules and the habitats
+    // of a plan, at the price point of the plan or the average
+    pub fn calc_build_cost (&self, responses: &[WareResponseExt], settings: &PlanSettings) -> Result<(Vec<BuildWare>, BuildTotal), Error> {
+        let mut modules
+            = responses.iter()
+              .filter_map(|r_ext| r_ext.ext.module.as_ref().map(|module| (module.ware_id.clone(), r_ext.response.new_modules)))
+              .collect::<Vec<_>>();
+        if let Some(habitats) = self.calc_habitats(responses, settings)? {
+            modules.push((habitats.ware_id, habitats.habitats));
+        }
+        Ok(self.data.calc_build_cost(&modules, settings.price.unwrap_or(PricePoint::Average))?)
+    }
+    // Storage of a plan per transport, empty without storage hours
+    pub fn calc_storage (&self, responses: &[WareResponseExt]) -> Vec<TransportStorage> {
+        self.data.calc_storage(responses.iter().map(|r_ext| &r_ext.ext))
+    }
+    // Production trees of the requested outputs of a plan
+    pub fn calc_production_tree (&self, responses: &[WareResponseExt]) -> Vec<ProductionNode> {
+        let rows = responses.iter().map(|r_ext| &r_ext.ext).collect::<Vec<_>>();
+        self.data.calc_production_tree(&rows)
+    }
+    pub fn fill_columns (&self, responses: &mut [WareResponseExt], columns: &[WareColumn]) {
+        for WareResponseExt{response, ext, ..} in responses.iter_mut() {
+            let info = &ext.single_ware.info;
+            for column in columns {
+                match column {
+                    WareColumn::Group        => response.group         = Some(info.group.clone().unwrap_or_default()),
+                    WareColumn::Volume       => response.volume        = Some(info.volume),
+                    WareColumn::PriceMin     => response.price_min     = Some(info.price_min),
+                    WareColumn::PriceAverage => response.price_average = Some(info.price_average),
+                    WareColumn::PriceMax     => response.price_max     = Some(info.price_max),
+                    WareColumn::Transport    => response.transport     = Some(info.transport.clone()),
+                    WareColumn::Tags         => response.tags          = Some(info.tags.join(" ")),
+                    WareColumn::Description  => response.description   = Some(self.data.get_ware_description(&info.ware_id).unwrap_or_default()),
+                    WareColumn::Factoryname  => response.factoryname   = Some(self.data.get_ware_factoryname(&info.ware_id).unwrap_or_default()),
+                }
+            }
+        }
     }
 }
 
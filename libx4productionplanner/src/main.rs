@@ -1,6 +1,7 @@
 use libx4productionplaner::*;
 
 #[derive(Debug)]
+#[allow(dead_code)] // fields are reported with Debug
 enum InputError {
     NoWaresInput,
     CsvError(csv::Error),
@@ -30,7 +31,7 @@ pub fn get_substr_byte_offset (sub_str: &str, base_str: &str) -> Option<usize> {
             sub_str.as_ptr().offset_from(base_str.as_ptr())
         ).ok();
         match offset_option {
-            Some(offset) if offset <= base_str.as_bytes().len() => {
+            Some(offset) if offset <= base_str.len() => {
                 offset_option
             }
             _ => None
@@ -38,10 +39,10 @@ pub fn get_substr_byte_offset (sub_str: &str, base_str: &str) -> Option<usize> {
     }
 }
 
-fn read_csv (csv: &str) -> Result<Vec<WareRequest>, InputError> {
+fn read_csv<T: serde::de::DeserializeOwned> (csv: &str) -> Result<Vec<T>, InputError> {
     // https://docs.rs/csv/latest/csv/cookbook/index.html
     let mut reader_builder = csv::ReaderBuilder::new();
-    reader_builder.delimiter(';' as u8);
+    reader_builder.delimiter(b';');
     let mut reader = reader_builder.from_reader(csv.as_bytes());
 
     let mut result = Vec::new();
@@ -55,7 +56,7 @@ fn write_csv<T: serde::Serialize> (wares: &[T]) -> Result<String, std::io::Error
     let mut data = Vec::<u8>::new();
     {
         let mut builder = csv::WriterBuilder::new();
-        builder.delimiter(';' as u8);
+        builder.delimiter(b';');
         let mut writer = builder.from_writer(&mut data);
         for w in wares {
             writer.serialize(w)?;
@@ -64,6 +65,15 @@ fn write_csv<T: serde::Serialize> (wares: &[T]) -> Result<String, std::io::Error
     }
     Ok(String::from_utf8(data).unwrap())
 }
+// One line per node, inputs indented under their consumer:
+// `Silicon Wafers (Universal): 100.00/min, 3.00 modules, 100.0% of the ware`
+fn write_tree (nodes: &[ProductionNode], depth: usize, out: &mut String) {
+    for node in nodes {
+        out.push_str(&format!("{}{} ({}): {:.2}/min, {:.2} modules, {:.1}% of the ware\n",
+                              "  ".repeat(depth), node.ware, node.method.as_deref().unwrap_or("Import"), node.wares_per_minute, node.modules, node.share_percent));
+        write_tree(&node.inputs, depth + 1, out);
+    }
+}
 
 #[derive(serde::Serialize, serde::Deserialize)]
 struct InputMeta {
@@ -72,12 +82,18 @@ struct InputMeta {
     prioritylist:       Vec<String>,
     #[serde(default)]
     blacklist:          Vec<String>,
+    // extra ware columns of the response
+    #[serde(default)]
+    columns:            Vec<WareColumn>,
+    #[serde(flatten)]
+    settings:           PlanSettings,
 }
-struct Input {
+// The csv rows are `WareRequest` for a request, `ModuleRequest` for a balance
+struct Input<T> {
     meta:         InputMeta,
-    ware_request: Vec<WareRequest>,
+    ware_request: Vec<T>,
 }
-impl Input {
+impl<T: serde::de::DeserializeOwned> Input<T> {
     fn load (input_str: String) -> Result<Self, InputError> {
         for remaining in input_str.lines().map(|line| &input_str[get_substr_byte_offset(line, &input_str).unwrap()..]) {
             if let Ok(ware_request) = read_csv(remaining) {
@@ -90,11 +106,77 @@ impl Input {
 }
 
 #[derive(Debug, clap::Args)]
-struct ArgsRequest {
+struct ArgsGame {
     #[arg(short, long)]
     gamedir: std::path::PathBuf,
+    /// The gamedir is a tree extracted by X Catalog Tool instead of the game installation
+    #[arg(long)]
+    unpacked: bool,
+    /// Load only these extensions (e.g. ego_dlc_boron), may be repeated
+    #[arg(long = "extension", conflicts_with_all = ["disabled_extensions", "no_extensions"])]
+    extensions: Vec<String>,
+    /// Load all extensions except these, may be repeated
+    #[arg(long = "disable-extension", conflicts_with = "no_extensions")]
+    disabled_extensions: Vec<String>,
+    /// Load the base game only
+    #[arg(long)]
+    no_extensions: bool,
+    /// Parse the game files even if they did not change since the last run
+    #[arg(long, conflicts_with = "cache_dir")]
+    no_cache: bool,
+    /// Where the parsed game data is kept between runs [default: <temp dir>/x4productionplanner]
+    #[arg(long)]
+    cache_dir: Option<std::path::PathBuf>,
+}
+impl ArgsGame {
+    fn extensions_selection (&self) -> ExtensionsSelection {
+        if self.no_extensions {
+            ExtensionsSelection::Only(Vec::new())
+        }
+        else if !self.extensions.is_empty() {
+            ExtensionsSelection::Only(self.extensions.clone())
+        }
+        else if !self.disabled_extensions.is_empty() {
+            ExtensionsSelection::AllExcept(self.disabled_extensions.clone())
+        }
+        else {
+            ExtensionsSelection::All
+        }
+    }
+    fn cache_dir_opt (&self) -> Option<std::path::PathBuf> {
+        if self.no_cache {
+            None
+        }
+        else {
+            Some(self.cache_dir.clone().unwrap_or_else(default_cache_dir))
+        }
+    }
+}
+
+#[derive(Debug, clap::Args)]
+struct ArgsRequest {
+    #[command(flatten)]
+    game: ArgsGame,
     #[arg(short, long)]
     request_file: std::path::PathBuf,
+    /// Prints the production tree of every requested output instead of the csv
+    #[arg(long)]
+    tree: bool,
+}
+
+#[derive(Debug, clap::Args)]
+struct ArgsBalance {
+    #[command(flatten)]
+    game: ArgsGame,
+    /// Same as a request file, with `ware;method;count` csv rows of the modules
+    #[arg(short, long)]
+    modules_file: std::path::PathBuf,
+}
+
+#[derive(Debug, clap::Args)]
+struct ArgsSources {
+    #[command(flatten)]
+    game: ArgsGame,
 }
 
 #[derive(Debug, clap::Parser)]
@@ -102,15 +184,24 @@ struct ArgsRequest {
 enum Args {
     Request(ArgsRequest),
     ExampleRequest,
+    /// Prints production, consumption and net per minute of the modules of a station
+    Balance(ArgsBalance),
+    /// Prints which catalog each loaded game file was taken from
+    Sources(ArgsSources),
 }
 #[derive(Debug)]
 enum InnerArgsWithGameKind {
-    Request(std::path::PathBuf),
+    Request(std::path::PathBuf, bool),
+    Balance(std::path::PathBuf),
+    Sources,
 }
 
 #[derive(Debug)]
 struct InnerArgsWithGame {
     gamedir: std::path::PathBuf,
+    unpacked: bool,
+    extensions: ExtensionsSelection,
+    cache_dir: Option<std::path::PathBuf>,
     kind: InnerArgsWithGameKind,
 }
 
@@ -124,11 +215,49 @@ impl From<Args> for InnerArgs {
     fn from(value: Args) -> Self {
         match value {
             Args::ExampleRequest => Self::ExampleRequest,
-            Args::Request(ArgsRequest{gamedir, request_file}) => Self::WithGame(InnerArgsWithGame{gamedir, kind: InnerArgsWithGameKind::Request(request_file)}),
+            Args::Request(ArgsRequest{game, request_file, tree}) => Self::WithGame(InnerArgsWithGame{extensions: game.extensions_selection(), cache_dir: game.cache_dir_opt(), unpacked: game.unpacked, gamedir: game.gamedir, kind: InnerArgsWithGameKind::Request(request_file, tree)}),
+            Args::Balance(ArgsBalance{game, modules_file}) => Self::WithGame(InnerArgsWithGame{extensions: game.extensions_selection(), cache_dir: game.cache_dir_opt(), unpacked: game.unpacked, gamedir: game.gamedir, kind: InnerArgsWithGameKind::Balance(modules_file)}),
+            Args::Sources(ArgsSources{game}) => Self::WithGame(InnerArgsWithGame{extensions: game.extensions_selection(), cache_dir: game.cache_dir_opt(), unpacked: game.unpacked, gamedir: game.gamedir, kind: InnerArgsWithGameKind::Sources}),
         }
     }
 }
 
+// Printed by `example-request`
+fn example_input () -> Input<WareRequest> {
+    Input {
+        meta: InputMeta {
+                  desired_unicode_id: Some("en".into()),
+                  prioritylist: vec!["Universal".into()],
+                  blacklist: vec!["Paranid".into()],
+                  columns: vec![WareColumn::Group, WareColumn::Factoryname],
+                  settings: PlanSettings {
+                                workforce_percent: 100.,
+                                price: Some(PricePoint::Average),
+                                workforce: Some(Workforce{method: None, habitat: None}),
+                                build_hours: Some(4.),
+                                storage_hours: Some(2.),
+                                // existing modules fix the method, so not of the split microchips
+                                existing_modules: vec![ExistingModules{ware: "Silicon Wafers".into(), method: "Universal".into(), count: 2}],
+                                external_supplies: vec![ExternalSupply{ware: "Energy Cells".into(), wares_per_minute: 500.}],
+                                import: vec!["Hull Parts".into()],
+                                miners: vec![MinerShip{transport: "solid".into(), cargo_volume: 7000., cycle_minutes: 15.}],
+                                ..Default::default()
+                            },
+              },
+        ware_request: vec![
+            WareRequest {
+                name: "Microchips".into(),
+                production_kind: CountsInput::WaresPerMinute(100.),
+                method: None,
+                blacklist: Vec::new(),
+                split: vec![MethodSplit{method: "Universal".into(), share: MethodShare::Ratio(0.6)}, MethodSplit{method: "Teladi".into(), share: MethodShare::Ratio(0.4)}],
+            },
+            WareRequest{name: "ARG S All-round Engine Mk1".into(), production_kind: CountsInput::Fabrics(1), method: None, blacklist: vec!["Terran".into()], split: Vec::new()},
+            WareRequest{name: "Silicon Wafers".into(), production_kind: CountsInput::WaresPerMinute(0.), method: Some("Universal".into()), blacklist: Vec::new(), split: Vec::new()},
+            ],
+    }
+}
+
 fn main () {
     use clap::Parser;
     //println!("hello-1");
@@ -139,44 +268,229 @@ fn main () {
 
     match args {
         InnerArgs::ExampleRequest => {
-            let example
-                = Input {
-                     meta: InputMeta {
-                               desired_unicode_id: Some("en".into()),
-                               prioritylist: vec!["Universal".into()],
-                               blacklist: vec!["Teladi".into()],
-                           },
-                     ware_request: vec![
-                         WareRequest{name: "Microchips".into(), production_kind: CountsInput::WaresPerMinute(100.)},
-                         WareRequest{name: "ARG S All-round Engine Mk1".into(), production_kind: CountsInput::Fabrics(1)},
-                         ],
-                     };
+            let example = example_input();
             let csv_str = write_csv(&example.ware_request).unwrap();
             //println!("deser: {:?}", read_csv(csv_str.trim()).unwrap());
             let toml_str = toml_edit::ser::to_string_pretty(&example.meta).unwrap();
             let example_request = format!("# These fields are optional\n{}# This csv is required\n{}", toml_str, csv_str);
             print!("{}", example_request);
         }
-        InnerArgs::WithGame(InnerArgsWithGame{gamedir, kind})
+        InnerArgs::WithGame(InnerArgsWithGame{gamedir, unpacked, extensions, cache_dir, kind})
             => {
-                let mut planner = X4ProductionPlanner::new(&gamedir).unwrap();
+                X4ProductionPlanner::check_gamedir(&gamedir, unpacked).unwrap();
+                let mut planner = X4ProductionPlanner::new_with_cache(&gamedir, unpacked, &extensions, cache_dir.as_deref()).unwrap();
                 match kind {
-                    InnerArgsWithGameKind::Request(request_file_path) => {
+                    InnerArgsWithGameKind::Request(request_file_path, is_tree) => {
                         let content = std::fs::read_to_string(request_file_path).unwrap();
-                        let input = Input::load(content).unwrap();
+                        let input = Input::<WareRequest>::load(content).unwrap();
 
-                        let result_ext
+                        let mut result_ext
                             = planner.calc_required_fabric_counts(
                                   input.meta.desired_unicode_id,
                                   input.ware_request,
                                   input.meta.prioritylist,
-                                  input.meta.blacklist
+                                  input.meta.blacklist,
+                                  &input.meta.settings
                                   ).unwrap();
+                        if is_tree {
+                            let mut tree_str = String::new();
+                            write_tree(&planner.calc_production_tree(&result_ext), 0, &mut tree_str);
+                            print!("{}", tree_str);
+                            return;
+                        }
+                        planner.fill_columns(&mut result_ext, &input.meta.columns);
+                        let (mining_wares, mining_transports) = planner.calc_mining(&result_ext, &input.meta.settings);
+                        let habitats_opt = planner.calc_habitats(&result_ext, &input.meta.settings).unwrap();
+                        let (build_wares, build_total) = planner.calc_build_cost(&result_ext, &input.meta.settings).unwrap();
+                        let storage = planner.calc_storage(&result_ext);
                         let result = result_ext.into_iter().map(|r_ext| r_ext.response).collect::<Vec<_>>();
                         let result_str = write_csv(&result).unwrap();
                         println!("{}", result_str);
+                        if !mining_wares.is_empty() {
+                            println!("{}", write_csv(&mining_wares).unwrap());
+                            println!("{}", write_csv(&mining_transports).unwrap());
+                        }
+                        if let Some(habitats) = habitats_opt {
+                            println!("{}", write_csv(&[habitats]).unwrap());
+                        }
+                        if !build_wares.is_empty() {
+                            println!("{}", write_csv(&build_wares).unwrap());
+                            println!("{}", write_csv(&[build_total]).unwrap());
+                        }
+                        if !storage.is_empty() {
+                            println!("{}", write_csv(&storage).unwrap());
+                        }
+                        if let Some(total) = EconomyTotal::from_responses(&result) {
+                            println!("{}", write_csv(&[total]).unwrap());
+                        }
+                    }
+                    InnerArgsWithGameKind::Balance(modules_file_path) => {
+                        let content = std::fs::read_to_string(modules_file_path).unwrap();
+                        let input = Input::<ModuleRequest>::load(content).unwrap();
+
+                        let mut result_ext
+                            = planner.calc_station_balance(
+                                  input.meta.desired_unicode_id,
+                                  input.ware_request,
+                                  &input.meta.settings
+                                  ).unwrap();
+                        planner.fill_columns(&mut result_ext, &input.meta.columns);
+                        let habitats_opt = planner.calc_habitats(&result_ext, &input.meta.settings).unwrap();
+                        let storage = planner.calc_storage(&result_ext);
+                        let result = result_ext.into_iter().map(|r_ext| r_ext.response).collect::<Vec<_>>();
+                        println!("{}", write_csv(&result).unwrap());
+                        if let Some(habitats) = habitats_opt {
+                            println!("{}", write_csv(&[habitats]).unwrap());
+                        }
+                        if !storage.is_empty() {
+                            println!("{}", write_csv(&storage).unwrap());
+                        }
+                        if let Some(total) = EconomyTotal::from_responses(&result) {
+                            println!("{}", write_csv(&[total]).unwrap());
+                        }
+                    }
+                    InnerArgsWithGameKind::Sources => {
+                        #[derive(serde::Serialize)]
+                        struct Source {
+                            file:    String,
+                            catalog: std::path::PathBuf,
+                        }
+                        let sources = planner.data.gen_sources_list().into_iter().map(|(file, catalog)| Source{file, catalog}).collect::<Vec<_>>();
+                        println!("{}", write_csv(&sources).unwrap());
                     }
                 }
             }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // the wares the example names, every produced ware has a module built
+    // of energy cells
+    const EXAMPLE_WARES: &str = r#"
+<languages>
+  <!-- English -->
+  <language id="44" name="English" voice="true" />
+</languages>
+<wares>
+  <production>
+    <method id="default" name="{20206,101}" />
+    <method id="paranid" name="{20206,301}">
+      <default race="paranid" />
+    </method>
+    <method id="teladi" name="{20206,401}">
+      <default race="teladi" />
+    </method>
+    <method id="terran" name="{20206,901}">
+      <default race="terran" />
+    </method>
+  </production>
+  <defaults id="default" name="default" transport="container" volume="1" tags="container">
+    <price min="1" average="1" max="1" />
+    <production time="10" amount="1" method="default" name="{20206,101}" />
+  </defaults>
+  <ware id="energycells" name="{20201,701}" group="energy" transport="container" volume="1" tags="container economy">
+    <price min="10" average="16" max="22" />
+    <production time="60" amount="175" method="default" name="{20206,101}" />
+  </ware>
+  <ware id="silicon" name="{20201,3501}" group="minerals" transport="solid" volume="10" tags="economy minable mineral solid">
+    <price min="111" average="130" max="150" />
+  </ware>
+  <ware id="siliconwafers" name="{20201,3601}" group="refined" transport="container" volume="18" tags="container economy">
+    <price min="180" average="299" max="419" />
+    <production time="180" amount="107" method="default" name="{20206,101}">
+      <primary>
+        <ware ware="energycells" amount="90" />
+        <ware ware="silicon" amount="240" />
+      </primary>
+    </production>
+  </ware>
+  <ware id="microchips" name="{20201,2201}" group="hightech" transport="container" volume="22" tags="container economy">
+    <price min="805" average="948" max="1090" />
+    <production time="600" amount="144" method="teladi" name="{20206,401}">
+      <primary>
+        <ware ware="energycells" amount="100" />
+        <ware ware="siliconwafers" amount="400" />
+      </primary>
+    </production>
+    <production time="600" amount="72" method="default" name="{20206,101}">
+      <primary>
+        <ware ware="energycells" amount="50" />
+        <ware ware="siliconwafers" amount="200" />
+      </primary>
+    </production>
+  </ware>
+  <ware id="hullparts" name="{20201,4001}" group="hightech" transport="container" volume="12" tags="container economy">
+    <price min="150" average="180" max="210" />
+    <production time="300" amount="100" method="default" name="{20206,101}">
+      <primary>
+        <ware ware="energycells" amount="80" />
+      </primary>
+    </production>
+  </ware>
+  <ware id="engine_arg_s_allround_01_mk1" name="{20201,5001}" group="engines" transport="equipment" volume="1" tags="equipment">
+    <price min="5000" average="6000" max="7000" />
+    <production time="60" amount="1" method="default" name="{20206,101}">
+      <primary>
+        <ware ware="hullparts" amount="10" />
+        <ware ware="microchips" amount="2" />
+      </primary>
+    </production>
+  </ware>
+  <ware id="workunit_busy" transport="workunit" volume="1" tags="noplayerbuild workunit">
+    <production time="600" amount="500" method="default" name="{20206,101}">
+      <primary>
+        <ware ware="energycells" amount="500" />
+      </primary>
+    </production>
+  </ware>
+  MODULES
+</wares>
+"#;
+    const EXAMPLE_TRANSLATIONS: &str = r#"
+<language id="44">
+  <page id="20201">
+    <t id="701">Energy Cells</t>
+    <t id="2201">Microchips</t>
+    <t id="3501">Silicon</t>
+    <t id="3601">Silicon Wafers</t>
+    <t id="4001">Hull Parts</t>
+    <t id="5001">ARG S All-round Engine Mk1</t>
+  </page>
+  <page id="20206">
+    <t id="101">Universal</t>
+    <t id="301">Paranid</t>
+    <t id="401">Teladi</t>
+    <t id="901">Terran</t>
+  </page>
+</language>
+"#;
+
+    #[test]
+    fn test_example_request () {
+        let module = |id: &str| format!(r#"<ware id="{}" transport="container" volume="1" tags="module">
+                                             <price min="1000" average="1000" max="1000" />
+                                             <production time="60" amount="1" method="default" name="{{20206,101}}">
+                                               <primary>
+                                                 <ware ware="energycells" amount="600" />
+                                               </primary>
+                                             </production>
+                                           </ware>"#, id);
+        let modules = ["module_gen_prod_energycells_01", "module_gen_prod_siliconwafers_01", "module_gen_prod_microchips_01", "module_tel_prod_microchips_01", "module_gen_prod_hullparts_01", "module_gen_prod_engine_arg_s_allround_01_mk1_01"].map(module).concat();
+        let mut planner = X4ProductionPlanner::new_from_data_str(EXAMPLE_WARES.replace("MODULES", &modules), EXAMPLE_TRANSLATIONS.to_string()).unwrap();
+
+        // as printed, so the request file the user starts from is valid
+        let example = example_input();
+        let example_str = format!("{}{}", toml_edit::ser::to_string_pretty(&example.meta).unwrap(), write_csv(&example.ware_request).unwrap());
+        let input = Input::<WareRequest>::load(example_str).unwrap();
+        let result_ext = planner.calc_required_fabric_counts(input.meta.desired_unicode_id, input.ware_request, input.meta.prioritylist, input.meta.blacklist, &input.meta.settings).unwrap();
+        let row = |name: &str, method: &str| result_ext.iter().find(|r_ext| r_ext.response.name == name && matches!(&r_ext.response.counts_output, CountsOutput::Produce(other, _) if other == method)).map(|r_ext| &r_ext.response);
+        assert!(row("Microchips", "Universal").is_some() && row("Microchips", "Teladi").is_some());
+        assert_eq!(row("Silicon Wafers", "Universal").unwrap().existing_modules, 2);
+        assert!(result_ext.iter().any(|r_ext| r_ext.response.name == "Hull Parts" && matches!(r_ext.response.counts_output, CountsOutput::Import)));
+        planner.calc_build_cost(&result_ext, &input.meta.settings).unwrap();
+        assert!(!planner.calc_storage(&result_ext).is_empty());
+    }
+}
@@ -0,0 +1,469 @@
+// X4 extensions patch game xml files with RFC 5261-like documents:
+// ```
+// <diff>
+//   <add sel="/wares">
+//     <ware id="spacefuel" ... />
+//   </add>
+//   <add sel="//ware[@id='energycells']/production" type="@name">{20206,101}</add>
+//   <replace sel="/wares/ware[@id='microchips']/price/@max">1200</replace>
+//   <remove sel="/wares/ware[@id='siliconwafers']/production[@method='teladi']" />
+// </diff>
+// ```
+// roxmltree is read-only, so the base document is copied to an owned tree,
+// patched and printed back to be parsed as usual.
+// Supported selectors are location paths of `/name` and `//name` (`*` for any)
+// steps with `[@attr='value']`, `[@attr]`, `[not(@attr)]` and `[N]`
+// predicates (joined with `and`), optionally ending with `/@attr`.
+
+#[derive(Debug)]
+pub enum XmlDiffError {
+    BadSelector(String),
+    SelectorNotFound(String),
+    SelectorAmbiguous(String, usize),
+    BadOperation(String),
+}
+
+#[derive(Debug, Clone)]
+enum XmlNode {
+    Element(XmlElement),
+    Text(String),
+    Comment(String),
+}
+#[derive(Debug, Clone)]
+struct XmlElement {
+    name:       String,
+    attributes: Vec<(String, String)>,
+    children:   Vec<XmlNode>,
+}
+impl XmlElement {
+    fn from_roxmltree (node: roxmltree::Node) -> Self {
+        let children
+            = node.children().filter_map(
+                  |child|
+                  if child.is_element() {
+                      Some(XmlNode::Element(Self::from_roxmltree(child)))
+                  }
+                  else if child.is_text() {
+                      child.text().map(|text| XmlNode::Text(text.to_string()))
+                  }
+                  else if child.is_comment() {
+                      child.text().map(|text| XmlNode::Comment(text.to_string()))
+                  }
+                  else {
+                      None
+                  }
+                  ).collect();
+        Self {
+            name:       node.tag_name().name().to_string(),
+            attributes: node.attributes().map(|atr| (atr.name().to_string(), atr.value().to_string())).collect(),
+            children,
+        }
+    }
+    fn attribute (&self, name: &str) -> Option<&str> {
+        self.attributes.iter().find_map(|(key, value)| (key == name).then_some(value.as_str()))
+    }
+    fn set_attribute (&mut self, name: &str, value: String) {
+        match self.attributes.iter_mut().find(|(key, _)| key == name) {
+            Some((_, existing)) => *existing = value,
+            None => self.attributes.push((name.to_string(), value)),
+        }
+    }
+    fn child_elements (&self) -> impl Iterator<Item=(usize, &XmlElement)> {
+        self.children.iter().enumerate().filter_map(|(idx, child)| match child {
+            XmlNode::Element(element) => Some((idx, element)),
+            _ => None,
+        })
+    }
+    fn get (&self, path: &[usize]) -> &XmlElement {
+        match path.split_first() {
+            None => self,
+            Some((idx, rest)) => match &self.children[*idx] {
+                XmlNode::Element(element) => element.get(rest),
+                _ => unreachable!(),
+            },
+        }
+    }
+    fn get_mut (&mut self, path: &[usize]) -> &mut XmlElement {
+        match path.split_first() {
+            None => self,
+            Some((idx, rest)) => match &mut self.children[*idx] {
+                XmlNode::Element(element) => element.get_mut(rest),
+                _ => unreachable!(),
+            },
+        }
+    }
+    fn write (&self, out: &mut String) {
+        out.push('<');
+        out.push_str(&self.name);
+        for (key, value) in self.attributes.iter() {
+            out.push_str(&format!(" {}=\"{}\"", key, escape(value)));
+        }
+        if self.children.is_empty() {
+            out.push_str("/>");
+            return;
+        }
+        out.push('>');
+        for child in self.children.iter() {
+            match child {
+                XmlNode::Element(element) => element.write(out),
+                XmlNode::Text(text) => out.push_str(&escape(text)),
+                XmlNode::Comment(text) => out.push_str(&format!("<!--{}-->", text)),
+            }
+        }
+        out.push_str(&format!("</{}>", self.name));
+    }
+}
+fn escape (input: &str) -> String {
+    input.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;").replace('"', "&quot;")
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Predicate {
+    HasAttribute(String),
+    NoAttribute(String),
+    AttributeEquals(String, String),
+    Position(usize),
+}
+#[derive(Debug, Clone)]
+struct Step {
+    is_descendant: bool,
+    name:          String,
+    predicates:    Vec<Predicate>,
+}
+// Byte index of the first `pattern` outside of quotes and nested brackets
+fn find_unquoted (input: &str, pattern: &str) -> Option<usize> {
+    let mut depth = 0;
+    let mut quote = None;
+    for (idx, c) in input.char_indices() {
+        match (c, quote) {
+            ('\'' | '"', None) => quote = Some(c),
+            (c, Some(q)) if c == q => quote = None,
+            (_, Some(_)) => (),
+            _ if depth == 0 && input[idx..].starts_with(pattern) => return Some(idx),
+            ('[', None) => depth += 1,
+            (']', None) => depth -= 1,
+            _ => (),
+        }
+    }
+    None
+}
+
+#[derive(Debug, Clone)]
+struct Selector {
+    steps:     Vec<Step>,
+    attribute: Option<String>,
+}
+impl Selector {
+    fn parse_predicate (input: &str, sel: &str) -> Result<Predicate, XmlDiffError> {
+        let bad = || XmlDiffError::BadSelector(sel.to_string());
+        let input = input.trim();
+        if let Ok(position) = input.parse::<usize>() {
+            return Ok(Predicate::Position(position));
+        }
+        if let Some(inner) = input.strip_prefix("not(").and_then(|rest| rest.strip_suffix(')')) {
+            let name = inner.trim().strip_prefix('@').ok_or_else(bad)?;
+            return Ok(Predicate::NoAttribute(name.to_string()));
+        }
+        let input = input.strip_prefix('@').ok_or_else(bad)?;
+        match input.split_once('=') {
+            None => Ok(Predicate::HasAttribute(input.to_string())),
+            Some((name, value)) => {
+                let value = value.trim();
+                let value = value.strip_prefix('\'').and_then(|v| v.strip_suffix('\''))
+                                .or_else(|| value.strip_prefix('"').and_then(|v| v.strip_suffix('"')))
+                                .ok_or_else(bad)?;
+                Ok(Predicate::AttributeEquals(name.trim().to_string(), value.to_string()))
+            }
+        }
+    }
+    fn parse (sel: &str) -> Result<Self, XmlDiffError> {
+        let bad = || XmlDiffError::BadSelector(sel.to_string());
+        let mut steps = Vec::new();
+        let mut attribute = None;
+        let mut remaining = sel.trim();
+        if !remaining.starts_with('/') {
+            return Err(bad());
+        }
+        while !remaining.is_empty() {
+            if attribute.is_some() {
+                // attribute must be the last step
+                return Err(bad());
+            }
+            let is_descendant = remaining.starts_with("//");
+            remaining = remaining.trim_start_matches('/');
+
+            // step ends at the next '/' outside of brackets and quotes
+            let end = find_unquoted(remaining, "/").unwrap_or(remaining.len());
+            let step = &remaining[..end];
+            remaining = &remaining[end..];
+
+            if let Some(name) = step.strip_prefix('@') {
+                attribute = Some(name.to_string());
+                continue;
+            }
+            let name_end = step.find('[').unwrap_or(step.len());
+            let name = step[..name_end].trim();
+            if name.is_empty() {
+                return Err(bad());
+            }
+            let mut predicates = Vec::new();
+            let mut rest = &step[name_end..];
+            while let Some(inner) = rest.strip_prefix('[') {
+                let close = find_unquoted(inner, "]").ok_or_else(bad)?;
+                let mut conditions = &inner[..close];
+                while let Some(and) = find_unquoted(conditions, " and ") {
+                    predicates.push(Self::parse_predicate(&conditions[..and], sel)?);
+                    conditions = &conditions[and+" and ".len()..];
+                }
+                predicates.push(Self::parse_predicate(conditions, sel)?);
+                rest = &inner[close+1..];
+            }
+            if !rest.trim().is_empty() {
+                return Err(bad());
+            }
+            steps.push(Step{is_descendant, name: name.to_string(), predicates});
+        }
+        if steps.is_empty() {
+            return Err(bad());
+        }
+        Ok(Self{steps, attribute})
+    }
+    fn matches (element: &XmlElement, predicate: &Predicate) -> bool {
+        match predicate {
+            Predicate::HasAttribute(name) => element.attribute(name).is_some(),
+            Predicate::NoAttribute(name) => element.attribute(name).is_none(),
+            Predicate::AttributeEquals(name, value) => element.attribute(name) == Some(value.as_str()),
+            Predicate::Position(_) => true,
+        }
+    }
+    fn collect_descendants (element: &XmlElement, path: &mut Vec<usize>, result: &mut Vec<Vec<usize>>) {
+        for (idx, child) in element.child_elements() {
+            path.push(idx);
+            result.push(path.clone());
+            Self::collect_descendants(child, path, result);
+            path.pop();
+        }
+    }
+    // children of `parent_path` matching the step, predicates apply in order
+    // and positions count among the children of the parent
+    fn select_children (document: &XmlElement, parent_path: &[usize], step: &Step) -> Vec<Vec<usize>> {
+        let mut matched
+            = document.get(parent_path).child_elements()
+              .filter(|(_, child)| step.name == "*" || step.name == child.name)
+              .map(|(idx, _)| [parent_path, &[idx]].concat())
+              .collect::<Vec<_>>();
+        for predicate in step.predicates.iter() {
+            matched = match predicate {
+                Predicate::Position(position) => matched.into_iter().nth(position.wrapping_sub(1)).into_iter().collect(),
+                _ => matched.into_iter().filter(|path| Self::matches(document.get(path), predicate)).collect(),
+            };
+        }
+        matched
+    }
+    // `document` is a nameless element with the root element as the only child
+    fn select (&self, document: &XmlElement) -> Vec<Vec<usize>> {
+        let mut context = vec![Vec::new()];
+        for step in self.steps.iter() {
+            let mut next = Vec::new();
+            for parent_path in context.iter() {
+                // `//name` is a child step from the context or any of its
+                // descendants
+                let mut parents = vec![parent_path.clone()];
+                if step.is_descendant {
+                    Self::collect_descendants(document.get(parent_path), &mut parent_path.clone(), &mut parents);
+                }
+                for parent in parents.iter() {
+                    next.extend(Self::select_children(document, parent, step));
+                }
+            }
+            next.sort();
+            next.dedup();
+            context = next;
+        }
+        context
+    }
+}
+
+fn apply_operation (document: &mut XmlElement, operation: roxmltree::Node) -> Result<(), XmlDiffError> {
+    let sel = operation.attribute("sel").ok_or(XmlDiffError::BadOperation(format!("{:?}", operation)))?;
+    let selector = Selector::parse(sel)?;
+    let mut selected = selector.select(document);
+    let path = match selected.len() {
+                   0 => return Err(XmlDiffError::SelectorNotFound(sel.to_string())),
+                   1 => selected.pop().unwrap(),
+                   count => return Err(XmlDiffError::SelectorAmbiguous(sel.to_string(), count)),
+               };
+    let text = || operation.text().unwrap_or("").trim().to_string();
+    let new_elements
+        = || operation.children().filter(|n| n.is_element()).map(|n| XmlNode::Element(XmlElement::from_roxmltree(n))).collect::<Vec<_>>();
+
+    match (operation.tag_name().name(), &selector.attribute) {
+        ("add", None) => {
+            if let Some(attribute) = operation.attribute("type").and_then(|t| t.strip_prefix('@')) {
+                document.get_mut(&path).set_attribute(attribute, text());
+                return Ok(());
+            }
+            match operation.attribute("pos").unwrap_or("append") {
+                "append" => document.get_mut(&path).children.extend(new_elements()),
+                "prepend" => {
+                    let element = document.get_mut(&path);
+                    element.children.splice(0..0, new_elements());
+                },
+                pos @ ("before" | "after") => {
+                    let (idx, parent_path) = path.split_last().unwrap();
+                    let idx = if pos == "before" { *idx } else { *idx + 1 };
+                    document.get_mut(parent_path).children.splice(idx..idx, new_elements());
+                },
+                _ => return Err(XmlDiffError::BadOperation(format!("{:?}", operation))),
+            }
+        },
+        ("replace", None) => {
+            let (idx, parent_path) = path.split_last().unwrap();
+            let mut replacement = new_elements();
+            if replacement.len() != 1 {
+                return Err(XmlDiffError::BadOperation(format!("{:?}", operation)));
+            }
+            document.get_mut(parent_path).children[*idx] = replacement.pop().unwrap();
+        },
+        ("replace", Some(attribute)) => {
+            let element = document.get_mut(&path);
+            if element.attribute(attribute).is_none() {
+                return Err(XmlDiffError::SelectorNotFound(sel.to_string()));
+            }
+            element.set_attribute(attribute, text());
+        },
+        ("remove", None) => {
+            let (idx, parent_path) = path.split_last().unwrap();
+            document.get_mut(parent_path).children.remove(*idx);
+        },
+        ("remove", Some(attribute)) => {
+            let element = document.get_mut(&path);
+            if element.attribute(attribute).is_none() {
+                return Err(XmlDiffError::SelectorNotFound(sel.to_string()));
+            }
+            element.attributes.retain(|(key, _)| key != attribute);
+        },
+        _ => return Err(XmlDiffError::BadOperation(format!("{:?}", operation))),
+    }
+    Ok(())
+}
+
+// Applies `<diff>` documents in the given order, returns the effective xml or
+// the index of the failed diff
+pub fn apply_diffs (base: &roxmltree::Document, diffs: &[&roxmltree::Document]) -> Result<String, (usize, XmlDiffError)> {
+    let mut document = XmlElement {
+                           name:       String::new(),
+                           attributes: Vec::new(),
+                           children:   vec![XmlNode::Element(XmlElement::from_roxmltree(base.root_element()))],
+                       };
+    for (idx, diff) in diffs.iter().enumerate() {
+        let root = diff.root_element();
+        if !root.has_tag_name("diff") {
+            return Err((idx, XmlDiffError::BadOperation(format!("{:?}", root))));
+        }
+        for operation in root.children().filter(|n| n.is_element()) {
+            apply_operation(&mut document, operation).map_err(|e| (idx, e))?;
+        }
+    }
+    let mut result = String::new();
+    document.get(&[0]).write(&mut result);
+    Ok(result)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const BASE: &str = r#"
+<wares>
+  <!-- comment & co -->
+  <ware id="energycells" volume="1" name="a]b">
+    <price min="10" max="22" />
+    <production time="60" amount="175" method="default" />
+  </ware>
+  <ware id="microchips" volume="22" name="x and y">
+    <price min="805" max="1090" />
+    <production time="600" amount="72" method="default" />
+    <production time="600" amount="72" method="teladi" />
+  </ware>
+</wares>
+"#;
+
+    fn patch (diff: &str) -> Result<String, XmlDiffError> {
+        let base = roxmltree::Document::parse(BASE).unwrap();
+        let diff = roxmltree::Document::parse(diff).unwrap();
+        apply_diffs(&base, &[&diff]).map_err(|(_, e)| e)
+    }
+
+    #[test]
+    fn test_diff_operations () {
+        let patched = patch(r#"<diff>
+            <add sel="/wares"><ware id="spacefuel" volume="4" /></add>
+            <add sel="/wares/ware[@id='energycells']" pos="before"><ware id="first" /></add>
+            <add sel="//ware[@id='microchips']/production[@method='teladi']" type="@name">A &amp; B</add>
+            <replace sel="/wares/ware[@id='microchips']/price/@max">1200</replace>
+            <replace sel="/wares/ware[@id='energycells']/production"><production time="30" amount="175" method="default" /></replace>
+            <remove sel="//ware[@id='microchips']/production[1]" />
+            <remove sel="/wares/ware[@id='energycells']/@volume" />
+        </diff>"#).unwrap();
+        let doc = roxmltree::Document::parse(&patched).unwrap();
+        let ids = doc.root_element().children().filter(|n| n.has_tag_name("ware")).map(|n| n.attribute("id").unwrap()).collect::<Vec<_>>();
+        assert_eq!(ids, ["first", "energycells", "microchips", "spacefuel"]);
+
+        let energycells = doc.descendants().find(|n| n.attribute("id") == Some("energycells")).unwrap();
+        assert_eq!(energycells.attribute("volume"), None);
+        assert_eq!(energycells.descendants().find(|n| n.has_tag_name("production")).unwrap().attribute("time"), Some("30"));
+
+        let microchips = doc.descendants().find(|n| n.attribute("id") == Some("microchips")).unwrap();
+        assert_eq!(microchips.descendants().find(|n| n.has_tag_name("price")).unwrap().attribute("max"), Some("1200"));
+        let productions = microchips.children().filter(|n| n.has_tag_name("production")).collect::<Vec<_>>();
+        assert_eq!(productions.len(), 1);
+        assert_eq!(productions[0].attribute("method"), Some("teladi"));
+        assert_eq!(productions[0].attribute("name"), Some("A & B"));
+    }
+    #[test]
+    fn test_diff_selectors () {
+        // brackets and "and" inside quotes are part of the value
+        let patched = patch(r#"<diff>
+            <add sel="/wares/ware[@name='a]b']" pos="after"><ware id="second" /></add>
+            <add sel="/wares/ware[@name='x and y' and @volume=&quot;22&quot;]" pos="prepend"><first /></add>
+            <add sel="//wares" type="@version">2</add>
+            <remove sel="//price[@min='10']" />
+        </diff>"#).unwrap();
+        let doc = roxmltree::Document::parse(&patched).unwrap();
+        assert_eq!(doc.root_element().attribute("version"), Some("2"));
+        let ids = doc.root_element().children().filter(|n| n.has_tag_name("ware")).map(|n| n.attribute("id").unwrap()).collect::<Vec<_>>();
+        assert_eq!(ids, ["energycells", "second", "microchips"]);
+        let microchips = doc.descendants().find(|n| n.attribute("id") == Some("microchips")).unwrap();
+        assert!(microchips.first_element_child().unwrap().has_tag_name("first"));
+        assert_eq!(doc.descendants().filter(|n| n.has_tag_name("price")).count(), 1);
+
+        let selector = Selector::parse("//ware[@name='x and y' and not(@id)][2]/@volume").unwrap();
+        assert_eq!(selector.steps[0].predicates, [Predicate::AttributeEquals("name".into(), "x and y".into()), Predicate::NoAttribute("id".into()), Predicate::Position(2)]);
+        assert_eq!(selector.attribute.as_deref(), Some("volume"));
+        assert!(matches!(Selector::parse("/wares/ware[@name='a]"), Err(XmlDiffError::BadSelector(_))));
+
+        // positions count per parent and predicates apply in order
+        let base = roxmltree::Document::parse(BASE).unwrap();
+        let document = XmlElement {
+                           name:       String::new(),
+                           attributes: Vec::new(),
+                           children:   vec![XmlNode::Element(XmlElement::from_roxmltree(base.root_element()))],
+                       };
+        let select = |sel: &str| Selector::parse(sel).unwrap().select(&document)
+                                     .iter().map(|path| document.get(path).attribute("method").unwrap().to_string())
+                                     .collect::<Vec<_>>();
+        assert_eq!(select("//production[1]"), ["default", "default"]);
+        assert_eq!(select("//production[2]"), ["teladi"]);
+        assert_eq!(select("//production[@method='teladi'][1]"), ["teladi"]);
+        assert!(select("//production[1][@method='teladi']").is_empty());
+        assert!(Selector::parse("/wares/ware[1][@id='microchips']").unwrap().select(&document).is_empty());
+        assert!(matches!(patch(r#"<diff><remove sel="//production[1]" /></diff>"#), Err(XmlDiffError::SelectorAmbiguous(_, 2))));
+    }
+    #[test]
+    fn test_diff_errors () {
+        assert!(matches!(patch(r#"<diff><remove sel="//ware[@id='unknown']" /></diff>"#), Err(XmlDiffError::SelectorNotFound(_))));
+        assert!(matches!(patch(r#"<diff><remove sel="//production" /></diff>"#), Err(XmlDiffError::SelectorAmbiguous(_, 3))));
+        assert!(matches!(patch(r#"<diff><remove sel="ware" /></diff>"#), Err(XmlDiffError::BadSelector(_))));
+    }
+}